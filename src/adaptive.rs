@@ -0,0 +1,124 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use crate::history::{Accuracy, Record};
+use crate::session::{Mode, RoundResult};
+
+/// Rounds taken into account when deciding to widen the note range.
+const LEVEL_WINDOW: usize = 10;
+/// Share of right answers in the window needed to widen the note range.
+const LEVEL_ACCURACY: f32 = 0.8;
+/// Semitones added to both sides of the note range per level.
+const LEVEL_STEP: u8 = 2;
+/// Half of the initial note range width.
+const INITIAL_HALF_WIDTH: u8 = 6;
+
+/// Past accuracy used to drill weak notes and intervals more often.
+///
+/// The note range starts as an octave in the middle of the configured range
+/// and widens every time enough recent rounds are answered right.
+#[derive(Default)]
+pub struct Adaptive {
+    notes: BTreeMap<u8, Accuracy>,
+    intervals: BTreeMap<u8, Accuracy>,
+    recent: VecDeque<bool>,
+    level: u8,
+}
+
+impl Adaptive {
+    /// Replays recorded rounds of the given mode.
+    pub fn new(records: &[Record], mode: Mode) -> Self {
+        let mut adaptive = Adaptive::default();
+        for record in records.iter().filter(|x| x.mode == mode) {
+            adaptive.add(record);
+        }
+        adaptive
+    }
+
+    pub fn record(&mut self, result: &RoundResult) {
+        self.add(&Record::new(result, 0, 0));
+    }
+
+    fn add(&mut self, record: &Record) {
+        for (note, correct) in record.note_results() {
+            self.notes.entry(note).or_default().add(correct);
+        }
+
+        let correct = record.score >= 1.0;
+        if record.mode == Mode::Interval && record.target.len() == 2 {
+            let interval = record.target[0].abs_diff(record.target[1]);
+            self.intervals.entry(interval).or_default().add(correct);
+        }
+
+        self.recent.push_back(correct);
+        if self.recent.len() > LEVEL_WINDOW {
+            self.recent.pop_front();
+        }
+        let recent_correct = self.recent.iter().filter(|&&x| x).count();
+        if self.recent.len() == LEVEL_WINDOW
+            && recent_correct as f32 >= LEVEL_ACCURACY * LEVEL_WINDOW as f32
+        {
+            self.level = self.level.saturating_add(1);
+            self.recent.clear();
+        }
+    }
+
+    /// Part of `min_note..=max_note` to generate notes from at the current level.
+    pub fn range(&self, min_note: u8, max_note: u8) -> (u8, u8) {
+        let center = min_note + (max_note - min_note) / 2;
+        let half_width = INITIAL_HALF_WIDTH.saturating_add(self.level.saturating_mul(LEVEL_STEP));
+        (
+            center.saturating_sub(half_width).max(min_note),
+            center.saturating_add(half_width).min(max_note),
+        )
+    }
+
+    /// Error rate of the note with add-one smoothing, 0.5 for unseen notes.
+    pub fn note_weight(&self, note: u8) -> f64 {
+        error_rate(self.notes.get(&note))
+    }
+
+    pub fn interval_weight(&self, semitones: u8) -> f64 {
+        error_rate(self.intervals.get(&semitones))
+    }
+}
+
+fn error_rate(accuracy: Option<&Accuracy>) -> f64 {
+    let Accuracy { correct, total, .. } = accuracy.copied().unwrap_or_default();
+    f64::from(total - correct + 1) / f64::from(total + 2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(target: u8, answer: u8) -> Record {
+        Record {
+            timestamp: 0,
+            mode: Mode::Note,
+            target: vec![target],
+            answer: vec![answer],
+            score: if target == answer { 1.0 } else { 0.0 },
+            replays: 0,
+            response_time_ms: 0,
+        }
+    }
+
+    #[test]
+    fn range_widens_with_accuracy() {
+        let mut records = vec![record(60, 60); LEVEL_WINDOW - 1];
+        let adaptive = Adaptive::new(&records, Mode::Note);
+        assert_eq!(adaptive.range(36, 96), (60, 72));
+
+        records.push(record(60, 60));
+        let adaptive = Adaptive::new(&records, Mode::Note);
+        assert_eq!(adaptive.range(36, 96), (58, 74));
+        assert_eq!(adaptive.range(60, 64), (60, 64));
+    }
+
+    #[test]
+    fn missed_notes_weigh_more() {
+        let adaptive = Adaptive::new(&[record(60, 60), record(62, 61)], Mode::Note);
+        assert!(adaptive.note_weight(62) > adaptive.note_weight(64));
+        assert!(adaptive.note_weight(64) > adaptive.note_weight(60));
+    }
+}
@@ -0,0 +1,432 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::Duration;
+
+use midir::{MidiIO, MidiInput, MidiInputConnection, MidiInputPort, MidiOutputConnection};
+
+use crate::midi::{ChannelFilter, KeyTracker};
+
+/// Note on status byte of channel 1.
+pub const NOTE_ON: u8 = 0x90;
+/// Note off status byte of channel 1.
+pub const NOTE_OFF: u8 = 0x80;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum KeyEvent {
+    /// Note and velocity of a pressed key.
+    Press(u8, u8),
+    Release(u8),
+}
+
+/// Source of key events played by the user.
+pub trait Input {
+    /// Waits for the next event, returns `None` if `timeout` passes first.
+    fn recv(&mut self, timeout: Option<Duration>) -> anyhow::Result<Option<KeyEvent>>;
+
+    /// Events that are already received, without waiting.
+    fn pending(&mut self) -> Vec<KeyEvent>;
+
+    /// Whether the played notes are picked up as well, like by a microphone,
+    /// so that events received during playback are not answers.
+    fn hears_output(&self) -> bool {
+        false
+    }
+}
+
+/// Sink for raw MIDI messages played to the user.
+pub trait Output {
+    fn send(&mut self, message: &[u8]) -> anyhow::Result<()>;
+
+    /// Lets time pass between messages, outputs rendering offline count it
+    /// instead of sleeping.
+    fn wait(&mut self, duration: Duration) -> anyhow::Result<()> {
+        std::thread::sleep(duration);
+        Ok(())
+    }
+}
+
+impl<T: Input + ?Sized> Input for &mut T {
+    fn recv(&mut self, timeout: Option<Duration>) -> anyhow::Result<Option<KeyEvent>> {
+        (**self).recv(timeout)
+    }
+
+    fn pending(&mut self) -> Vec<KeyEvent> {
+        (**self).pending()
+    }
+
+    fn hears_output(&self) -> bool {
+        (**self).hears_output()
+    }
+}
+
+impl<T: Input + ?Sized> Input for Box<T> {
+    fn recv(&mut self, timeout: Option<Duration>) -> anyhow::Result<Option<KeyEvent>> {
+        (**self).recv(timeout)
+    }
+
+    fn pending(&mut self) -> Vec<KeyEvent> {
+        (**self).pending()
+    }
+
+    fn hears_output(&self) -> bool {
+        (**self).hears_output()
+    }
+}
+
+impl<T: Output + ?Sized> Output for &mut T {
+    fn send(&mut self, message: &[u8]) -> anyhow::Result<()> {
+        (**self).send(message)
+    }
+
+    fn wait(&mut self, duration: Duration) -> anyhow::Result<()> {
+        (**self).wait(duration)
+    }
+}
+
+impl<T: Output + ?Sized> Output for Box<T> {
+    fn send(&mut self, message: &[u8]) -> anyhow::Result<()> {
+        (**self).send(message)
+    }
+
+    fn wait(&mut self, duration: Duration) -> anyhow::Result<()> {
+        (**self).wait(duration)
+    }
+}
+
+impl<T: Output + ?Sized> Output for Arc<Mutex<T>> {
+    fn send(&mut self, message: &[u8]) -> anyhow::Result<()> {
+        self.lock().unwrap().send(message)
+    }
+
+    fn wait(&mut self, duration: Duration) -> anyhow::Result<()> {
+        self.lock().unwrap().wait(duration)
+    }
+}
+
+/// MIDI port given by its index or by a part of its name.
+#[derive(Clone, Debug)]
+pub enum PortSelector {
+    Index(usize),
+    Name(String),
+}
+
+impl FromStr for PortSelector {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err("port cannot be empty".to_string());
+        }
+        Ok(match s.parse() {
+            Ok(index) => PortSelector::Index(index),
+            Err(_) => PortSelector::Name(s.to_string()),
+        })
+    }
+}
+
+impl PortSelector {
+    /// Finds the port, a name matches exactly or as a unique case-insensitive
+    /// substring.
+    pub fn find<T: MidiIO>(&self, io: &T) -> anyhow::Result<T::Port> {
+        let ports = io.ports();
+        match self {
+            PortSelector::Index(index) => ports
+                .get(*index)
+                .cloned()
+                .ok_or_else(|| anyhow::anyhow!("No MIDI port with number {}", index)),
+            PortSelector::Name(name) => {
+                let names = port_names(io);
+                if let Some(i) = names.iter().position(|x| x == name) {
+                    return Ok(ports[i].clone());
+                }
+
+                let lowercase = name.to_lowercase();
+                let matches: Vec<usize> = (0..names.len())
+                    .filter(|&i| names[i].to_lowercase().contains(&lowercase))
+                    .collect();
+                match *matches.as_slice() {
+                    [i] => Ok(ports[i].clone()),
+                    [] => anyhow::bail!("No MIDI port matches `{}`", name),
+                    _ => anyhow::bail!(
+                        "MIDI port `{}` is ambiguous: {}",
+                        name,
+                        matches
+                            .iter()
+                            .map(|&i| names[i].as_str())
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ),
+                }
+            }
+        }
+    }
+}
+
+/// Names of all ports, in the order of their indices.
+pub fn port_names<T: MidiIO>(io: &T) -> Vec<String> {
+    io.ports()
+        .iter()
+        .map(|p| io.port_name(p).unwrap_or_else(|_| "<unknown>".to_string()))
+        .collect()
+}
+
+/// Port with this name, if it is connected.
+///
+/// ALSA appends the client and port numbers to the names (`... 20:0`), which
+/// change when devices are connected in another order, so a port that only
+/// differs in them matches too if it is the only one.
+pub fn find_port<T: MidiIO>(io: &T, name: &str) -> Option<T::Port> {
+    let i = position_by_name(&port_names(io), name)?;
+    io.ports().get(i).cloned()
+}
+
+fn position_by_name(names: &[String], name: &str) -> Option<usize> {
+    if let Some(i) = names.iter().position(|x| x == name) {
+        return Some(i);
+    }
+    let base = without_client_id(name);
+    let matches: Vec<usize> = (0..names.len())
+        .filter(|&i| without_client_id(&names[i]) == base)
+        .collect();
+    match *matches.as_slice() {
+        [i] => Some(i),
+        _ => None,
+    }
+}
+
+/// Name without a trailing ALSA `client:port` id.
+fn without_client_id(name: &str) -> &str {
+    let is_id = |x: &str| {
+        let mut numbers = x.split(':');
+        numbers.clone().count() == 2
+            && numbers.all(|x| !x.is_empty() && x.bytes().all(|b| b.is_ascii_digit()))
+    };
+    match name.rsplit_once(' ') {
+        Some((base, id)) if is_id(id) => base,
+        _ => name,
+    }
+}
+
+/// Names of the ports used last time, to reconnect to them on the next start
+/// even if their numbers changed.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LastPorts {
+    pub input: Option<String>,
+    pub output: Option<String>,
+}
+
+impl LastPorts {
+    /// `guess-note/ports.tsv` in the user data directory.
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::data_dir().map(|x| x.join("guess-note").join("ports.tsv"))
+    }
+
+    /// Reads the saved names, none if the file does not exist yet.
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        match fs::read_to_string(path) {
+            Ok(text) => Ok(Self::parse(&text)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, self.to_string())?;
+        Ok(())
+    }
+
+    fn parse(text: &str) -> Self {
+        let mut ports = Self::default();
+        for line in text.lines() {
+            match line.split_once('\t') {
+                Some(("input", name)) => ports.input = Some(name.to_string()),
+                Some(("output", name)) => ports.output = Some(name.to_string()),
+                _ => {}
+            }
+        }
+        ports
+    }
+}
+
+impl fmt::Display for LastPorts {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(name) = &self.input {
+            writeln!(f, "input\t{}", name)?;
+        }
+        if let Some(name) = &self.output {
+            writeln!(f, "output\t{}", name)?;
+        }
+        Ok(())
+    }
+}
+
+pub struct MidirInput {
+    _conn: MidiInputConnection<()>,
+    rx: mpsc::Receiver<KeyEvent>,
+}
+
+impl MidirInput {
+    /// Connects to the port, taking notes only from channels matching `channels`.
+    pub fn connect(
+        midi_in: MidiInput,
+        port: &MidiInputPort,
+        channels: ChannelFilter,
+    ) -> anyhow::Result<Self> {
+        let (tx, rx) = mpsc::channel();
+        let mut tracker = KeyTracker::new(channels);
+
+        let conn = midi_in
+            .connect(
+                port,
+                "guess-note-input",
+                move |_, message, _| {
+                    for event in tracker.push(message) {
+                        let _ = tx.send(event);
+                    }
+                },
+                (),
+            )
+            .map_err(|e| anyhow::anyhow!("cannot connect to input port: {}", e))?;
+
+        Ok(MidirInput { _conn: conn, rx })
+    }
+}
+
+/// Waits for an event sent from a device callback.
+pub(crate) fn recv_event(
+    rx: &mpsc::Receiver<KeyEvent>,
+    timeout: Option<Duration>,
+) -> anyhow::Result<Option<KeyEvent>> {
+    match timeout {
+        Some(timeout) => match rx.recv_timeout(timeout) {
+            Ok(event) => Ok(Some(event)),
+            Err(mpsc::RecvTimeoutError::Timeout) => Ok(None),
+            Err(e) => Err(e.into()),
+        },
+        None => Ok(Some(rx.recv()?)),
+    }
+}
+
+impl Input for MidirInput {
+    fn recv(&mut self, timeout: Option<Duration>) -> anyhow::Result<Option<KeyEvent>> {
+        recv_event(&self.rx, timeout)
+    }
+
+    fn pending(&mut self) -> Vec<KeyEvent> {
+        self.rx.try_iter().collect()
+    }
+}
+
+pub struct MidirOutput(pub MidiOutputConnection);
+
+impl Output for MidirOutput {
+    fn send(&mut self, message: &[u8]) -> anyhow::Result<()> {
+        self.0
+            .send(message)
+            .map_err(|_| anyhow::anyhow!("cannot play note"))
+    }
+}
+
+/// Input without any keys, for answers typed at the prompt.
+///
+/// Waiting with a timeout times out right away and waiting without one fails.
+pub struct NoInput;
+
+impl Input for NoInput {
+    fn recv(&mut self, timeout: Option<Duration>) -> anyhow::Result<Option<KeyEvent>> {
+        match timeout {
+            Some(_) => Ok(None),
+            None => anyhow::bail!("There is no input to play answers on"),
+        }
+    }
+
+    fn pending(&mut self) -> Vec<KeyEvent> {
+        Vec::new()
+    }
+}
+
+/// Input replaying a fixed list of events, e.g. for tests.
+///
+/// Events are only delivered when waited for, so `pending` is always empty.
+/// Once the script is over, waiting with a timeout times out and waiting
+/// without one fails.
+pub struct ScriptedInput {
+    events: VecDeque<KeyEvent>,
+}
+
+impl ScriptedInput {
+    pub fn new(events: impl IntoIterator<Item = KeyEvent>) -> Self {
+        ScriptedInput {
+            events: events.into_iter().collect(),
+        }
+    }
+}
+
+impl Input for ScriptedInput {
+    fn recv(&mut self, timeout: Option<Duration>) -> anyhow::Result<Option<KeyEvent>> {
+        match self.events.pop_front() {
+            Some(event) => Ok(Some(event)),
+            None if timeout.is_some() => Ok(None),
+            None => anyhow::bail!("input script is over"),
+        }
+    }
+
+    fn pending(&mut self) -> Vec<KeyEvent> {
+        Vec::new()
+    }
+}
+
+/// Output that keeps every sent message.
+#[derive(Default)]
+pub struct RecordingOutput {
+    pub messages: Vec<Vec<u8>>,
+}
+
+impl Output for RecordingOutput {
+    fn send(&mut self, message: &[u8]) -> anyhow::Result<()> {
+        self.messages.push(message.to_vec());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn last_ports_roundtrip() {
+        let ports = LastPorts {
+            input: Some("USB Keyboard:USB Keyboard MIDI 1 20:0".to_string()),
+            output: None,
+        };
+        assert_eq!(LastPorts::parse(&ports.to_string()), ports);
+        assert_eq!(LastPorts::parse(""), LastPorts::default());
+    }
+
+    #[test]
+    fn port_found_with_other_client_id() {
+        let names: Vec<String> = vec![
+            "Midi Through:Midi Through Port-0 14:0".to_string(),
+            "USB Keyboard:USB Keyboard MIDI 1 24:0".to_string(),
+        ];
+        let find = |name| position_by_name(&names, name);
+        assert_eq!(find("USB Keyboard:USB Keyboard MIDI 1 24:0"), Some(1));
+        assert_eq!(find("USB Keyboard:USB Keyboard MIDI 1 20:0"), Some(1));
+        assert_eq!(find("USB Keyboard:USB Keyboard MIDI 2 20:1"), None);
+
+        let twice = vec![names[1].clone(), names[1].replace("24:0", "28:0")];
+        assert_eq!(
+            position_by_name(&twice, "USB Keyboard:USB Keyboard MIDI 1 20:0"),
+            None
+        );
+    }
+}
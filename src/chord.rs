@@ -0,0 +1,57 @@
+pub struct ChordKind {
+    pub name: &'static str,
+    pub intervals: &'static [u8],
+}
+
+pub static TRIADS: [ChordKind; 4] = [
+    ChordKind {
+        name: "major",
+        intervals: &[0, 4, 7],
+    },
+    ChordKind {
+        name: "minor",
+        intervals: &[0, 3, 7],
+    },
+    ChordKind {
+        name: "diminished",
+        intervals: &[0, 3, 6],
+    },
+    ChordKind {
+        name: "augmented",
+        intervals: &[0, 4, 8],
+    },
+];
+
+pub static SEVENTHS: [ChordKind; 5] = [
+    ChordKind {
+        name: "major seventh",
+        intervals: &[0, 4, 7, 11],
+    },
+    ChordKind {
+        name: "dominant seventh",
+        intervals: &[0, 4, 7, 10],
+    },
+    ChordKind {
+        name: "minor seventh",
+        intervals: &[0, 3, 7, 10],
+    },
+    ChordKind {
+        name: "half-diminished seventh",
+        intervals: &[0, 3, 6, 10],
+    },
+    ChordKind {
+        name: "diminished seventh",
+        intervals: &[0, 3, 6, 9],
+    },
+];
+
+impl ChordKind {
+    /// Distance between the lowest and the highest chord tone.
+    pub fn span(&self) -> u8 {
+        self.intervals.last().copied().unwrap_or(0)
+    }
+
+    pub fn notes(&self, root: u8) -> Vec<u8> {
+        self.intervals.iter().map(|x| root + x).collect()
+    }
+}
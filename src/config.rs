@@ -0,0 +1,185 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use toml::value::{Table, Value};
+
+/// Preferred settings read from a TOML file.
+///
+/// Keys are the long command line flags, with either dashes or underscores
+/// (`min_note = 40`, `harmonic = true`, `input-port = "Keystation"`).
+/// A `[profiles.<name>]` table holds the same keys and overrides the
+/// top-level ones when the profile is selected. A switch turned on in the file
+/// is turned off with `--no-<switch>` on the command line.
+pub struct Config {
+    table: Table,
+}
+
+impl Config {
+    /// `guess-note/config.toml` in the user config directory.
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|x| x.join("guess-note").join("config.toml"))
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let text =
+            fs::read_to_string(path).with_context(|| format!("cannot open {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("cannot parse {}", path.display()))
+    }
+
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        Ok(Config {
+            table: toml::from_str(text)?,
+        })
+    }
+
+    pub fn profiles(&self) -> Vec<&str> {
+        match self.table.get("profiles") {
+            Some(Value::Table(profiles)) => profiles.keys().map(String::as_str).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Command line flags for the settings of the profile, or of the top level
+    /// only without one.
+    ///
+    /// Flags already present in `given`, or negated there, are left out, so
+    /// that the command line overrides the file.
+    pub fn args(&self, profile: Option<&str>, given: &[String]) -> anyhow::Result<Vec<String>> {
+        let mut args = Vec::new();
+        for (flag, value) in self.values(profile)? {
+            if given.contains(&flag) || given.contains(&negated(&flag)) {
+                continue;
+            }
+            match value {
+                Value::Boolean(true) => args.push(flag),
+                Value::Boolean(false) => {}
+                Value::String(x) => args.extend(vec![flag, x]),
+                Value::Integer(x) => args.extend(vec![flag, x.to_string()]),
+                Value::Float(x) => args.extend(vec![flag, x.to_string()]),
+                _ => anyhow::bail!("Unsupported value for `{}`", &flag[2..]),
+            }
+        }
+        Ok(args)
+    }
+
+    /// The `given` arguments without the `--no-<switch>` ones negating
+    /// switches of the file, which are not flags of their own.
+    pub fn without_negations(
+        &self,
+        profile: Option<&str>,
+        given: &[String],
+    ) -> anyhow::Result<Vec<String>> {
+        let negations: Vec<String> = self
+            .values(profile)?
+            .into_iter()
+            .filter(|(_, value)| value.is_bool())
+            .map(|(flag, _)| negated(&flag))
+            .collect();
+        Ok(given
+            .iter()
+            .filter(|x| !negations.contains(x))
+            .cloned()
+            .collect())
+    }
+
+    /// Values of the profile over the top level ones by their flags.
+    fn values(&self, profile: Option<&str>) -> anyhow::Result<BTreeMap<String, Value>> {
+        let mut values = BTreeMap::new();
+        let mut add = |table: &Table| {
+            for (key, value) in table {
+                if key != "profiles" {
+                    values.insert(format!("--{}", key.replace('_', "-")), value.clone());
+                }
+            }
+        };
+
+        add(&self.table);
+        if let Some(name) = profile {
+            match self.table.get("profiles").and_then(|x| x.get(name)) {
+                Some(Value::Table(table)) => add(table),
+                _ => anyhow::bail!(
+                    "Unknown profile `{}`, expected one of: {}",
+                    name,
+                    self.profiles().join(", ")
+                ),
+            }
+        }
+        Ok(values)
+    }
+}
+
+/// `--no-<name>` for `--<name>`.
+fn negated(flag: &str) -> String {
+    format!("--no-{}", &flag[2..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+        input_port = "Keystation"
+        min-note = 48
+        harmonic = true
+        answer_both = false
+
+        [profiles.beginner]
+        min_note = 60
+        max_note = 72
+    "#;
+
+    #[test]
+    fn profile_overrides_top_level() {
+        let config = Config::parse(CONFIG).unwrap();
+        assert_eq!(
+            config.args(None, &[]).unwrap(),
+            vec![
+                "--harmonic",
+                "--input-port",
+                "Keystation",
+                "--min-note",
+                "48"
+            ]
+        );
+        assert_eq!(
+            config.args(Some("beginner"), &[]).unwrap(),
+            vec![
+                "--harmonic",
+                "--input-port",
+                "Keystation",
+                "--max-note",
+                "72",
+                "--min-note",
+                "60"
+            ]
+        );
+        assert!(config.args(Some("expert"), &[]).is_err());
+    }
+
+    #[test]
+    fn command_line_overrides_file() {
+        let config = Config::parse(CONFIG).unwrap();
+        let given = vec!["--min-note".to_string(), "40".to_string()];
+        assert_eq!(
+            config.args(None, &given).unwrap(),
+            vec!["--harmonic", "--input-port", "Keystation"]
+        );
+    }
+
+    #[test]
+    fn command_line_turns_switches_off() {
+        let config = Config::parse(CONFIG).unwrap();
+        let given = vec!["--no-harmonic".to_string(), "--no-history".to_string()];
+        assert_eq!(
+            config.args(None, &given).unwrap(),
+            vec!["--input-port", "Keystation", "--min-note", "48"]
+        );
+        assert_eq!(
+            config.without_negations(None, &given).unwrap(),
+            vec!["--no-history"]
+        );
+    }
+}
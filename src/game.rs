@@ -0,0 +1,935 @@
+use std::collections::HashSet;
+use std::io::BufRead;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::backend::{Input, KeyEvent, Output};
+use crate::history::{History, Record};
+use crate::instrument::{program_name, Instrument};
+use crate::midi::Message;
+use crate::note::Note;
+use crate::playback::{Articulation, Dynamic, Velocity};
+use crate::session::{Mode, Round, RoundResult, Session};
+use crate::summary::Summary;
+
+/// Velocity of note offs.
+const VELOCITY: u8 = 0x40;
+const BANK_SELECT_MSB: u8 = 0;
+const BANK_SELECT_LSB: u8 = 32;
+const SUSTAIN_PEDAL: u8 = 64;
+/// Hints in the order they are given: octave, key color and reference C.
+const HINT_COUNT: u32 = 3;
+const MIDDLE_C: u8 = 60;
+const BLACK_KEYS: [u8; 5] = [1, 3, 6, 8, 10];
+
+/// What was typed at the answer prompt.
+enum Typed {
+    Answer(Vec<u8>),
+    Replay,
+    Hint,
+}
+
+/// Interactive game loop playing rounds of a session over MIDI.
+pub struct Game<I, O, P> {
+    session: Session,
+    input: I,
+    output: O,
+    prompt: P,
+    history: Option<History>,
+    summary: Arc<Mutex<Summary>>,
+    /// Program last selected on the output.
+    program: Option<u8>,
+    /// Target notes left sounding until the answer.
+    sustained: Vec<u8>,
+}
+
+impl<I: Input, O: Output, P: BufRead> Game<I, O, P> {
+    pub fn new(session: Session, input: I, output: O, prompt: P) -> Self {
+        let summary = Summary {
+            notation: session.settings().notation,
+            ..Summary::default()
+        };
+        Game {
+            session,
+            input,
+            output,
+            prompt,
+            history: None,
+            summary: Arc::new(Mutex::new(summary)),
+            program: None,
+            sustained: Vec::new(),
+        }
+    }
+
+    /// Saves every played round to `history`.
+    pub fn with_history(mut self, history: History) -> Self {
+        self.history = Some(history);
+        self
+    }
+
+    /// Totals of the played rounds, shared to be printed e.g. on interrupt.
+    pub fn summary(&self) -> Arc<Mutex<Summary>> {
+        self.summary.clone()
+    }
+
+    /// Plays rounds until the round count or the time limit is reached.
+    pub fn run(&mut self) -> anyhow::Result<()> {
+        let started = Instant::now();
+        let settings = self.session.settings();
+        let rounds = settings.rounds;
+        let time_limit = settings.time_limit_s.map(Duration::from_secs);
+
+        let mut played = 0;
+        while rounds.is_none_or(|x| played < x) && time_limit.is_none_or(|x| started.elapsed() < x)
+        {
+            self.play_round()?;
+            played += 1;
+        }
+        Ok(())
+    }
+
+    /// Plays a single round and prints its result.
+    pub fn play_round(&mut self) -> anyhow::Result<RoundResult> {
+        let settings = self.session.settings();
+        match settings.mode {
+            Mode::Note => println!("\n ~~ Guess the note! ~~"),
+            Mode::Interval => println!("\n ~~ Guess the interval! ~~"),
+            Mode::Chord => println!("\n ~~ Guess the chord! ~~"),
+            Mode::Melody => println!("\n ~~ Guess the melody! ~~"),
+            Mode::Dynamics => println!("\n ~~ Guess the dynamics! ~~"),
+        }
+
+        let round = self.session.next_round();
+        if let Some(instrument) = self.session.settings().instrument {
+            let program = instrument.program(self.program);
+            if self.program != Some(program) {
+                self.select_program(program)?;
+            }
+            if instrument == Instrument::Random {
+                println!("Instrument: {}", program_name(program));
+            }
+        }
+
+        let settings = self.session.settings();
+        if settings.cadence {
+            let cadence = settings.scale.cadence(settings.key.0);
+            let cadence_chord_ms = settings.cadence_chord_ms;
+            let velocity = settings.velocity;
+            for chord in &cadence {
+                self.play(chord, true, cadence_chord_ms, velocity, false)?;
+            }
+            self.wait_ms(cadence_chord_ms)?;
+        }
+
+        if let Some(reference) = round.reference() {
+            println!(
+                "Reference note: {}",
+                self.session.settings().notation.note(Note(reference))
+            );
+        }
+        self.play_target(&round)?;
+
+        let mut replays = 0;
+        let mut hints = 0;
+        let mut started = Instant::now();
+        let typed_answers = self.session.settings().typed_answers;
+        let mut notes = if typed_answers {
+            loop {
+                match self.read_typed_answer(&round)? {
+                    Typed::Answer(notes) => break notes,
+                    Typed::Replay => {
+                        self.play_target(&round)?;
+                        replays += 1;
+                    }
+                    Typed::Hint => {
+                        self.hint(&round, hints)?;
+                        hints = (hints + 1).min(HINT_COUNT);
+                    }
+                }
+                started = Instant::now();
+            }
+        } else {
+            self.capture_answer(&round)?
+        };
+        self.release_sustained()?;
+        let mut response_time = started.elapsed();
+        // Typed answers are confirmed by the enter key already.
+        if !self.session.settings().non_interactive && !typed_answers {
+            loop {
+                let played = match round.mode() {
+                    Mode::Dynamics => format!(
+                        "dynamics is {} (velocity {})",
+                        Dynamic::from_velocity(notes[0]),
+                        notes[0]
+                    ),
+                    _ => format!(
+                        "{} {}",
+                        if notes.len() == 1 {
+                            "note is"
+                        } else {
+                            "notes are"
+                        },
+                        self.session.settings().notation.notes(&notes)
+                    ),
+                };
+                println!(
+                    "Last played {}. Confirm your guess? y/n, or ? for a hint",
+                    played
+                );
+                match self.read_line()?.trim().to_lowercase().as_str() {
+                    "y" => break,
+                    // The played answer stays until it is confirmed or not.
+                    "?" => {
+                        self.hint(&round, hints)?;
+                        hints = (hints + 1).min(HINT_COUNT);
+                        continue;
+                    }
+                    _ => {
+                        self.play_target(&round)?;
+                        replays += 1;
+                    }
+                }
+
+                started = Instant::now();
+                notes = self.capture_answer(&round)?;
+                response_time = started.elapsed();
+            }
+        }
+
+        let result = round.submit(&notes).with_hints(hints);
+        println!("{}", result);
+        self.session.record(&result);
+        self.summary.lock().unwrap().add(&result, response_time);
+
+        if let Some(history) = &self.history {
+            let record = Record::new(&result, replays, response_time.as_millis() as u64);
+            history.append(&record)?;
+        }
+
+        Ok(result)
+    }
+
+    fn read_line(&mut self) -> anyhow::Result<String> {
+        let mut input = String::new();
+        if self.prompt.read_line(&mut input)? == 0 {
+            anyhow::bail!("Prompt input is closed");
+        }
+        Ok(input)
+    }
+
+    /// Gives the hint with the given index about the first target note,
+    /// repeating the last one once they are over.
+    fn hint(&mut self, round: &Round, index: u32) -> anyhow::Result<()> {
+        let (what, note) = match round.mode() {
+            Mode::Dynamics => return self.dynamics_hint(round),
+            Mode::Note => ("The note", round.target()[0]),
+            Mode::Interval => ("The top note", round.target()[1]),
+            Mode::Chord => ("The root", round.target()[0]),
+            Mode::Melody => ("The first note", round.target()[0]),
+        };
+        let notation = self.session.settings().notation;
+        match index {
+            0 => println!(
+                "Hint: {} is in octave {}",
+                what.to_lowercase(),
+                notation.octave(Note(note))
+            ),
+            1 => println!(
+                "Hint: {} is a {} key",
+                what.to_lowercase(),
+                if BLACK_KEYS.contains(&(note % 12)) {
+                    "black"
+                } else {
+                    "white"
+                }
+            ),
+            _ => {
+                println!(
+                    "Hint: here is {} for reference",
+                    notation.note(Note(MIDDLE_C))
+                );
+                let settings = self.session.settings();
+                let (duration_ms, velocity) = (settings.guess_play_duration_ms, settings.velocity);
+                self.play(&[MIDDLE_C], false, duration_ms, velocity, false)?;
+                self.wait_ms(duration_ms)?;
+                self.play_target(round)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Plays the target note at mf for comparison before replaying it.
+    fn dynamics_hint(&mut self, round: &Round) -> anyhow::Result<()> {
+        println!("Hint: here is {} for comparison", Dynamic::MEZZO_FORTE);
+        let duration_ms = self.session.settings().guess_play_duration_ms;
+        let velocity = Velocity::Fixed(Dynamic::MEZZO_FORTE.velocity());
+        self.play(round.target(), false, duration_ms, velocity, false)?;
+        self.wait_ms(duration_ms)?;
+        self.play_target(round)
+    }
+
+    /// Reads note names, or a dynamic marking in dynamics mode, from the
+    /// prompt.
+    ///
+    /// Names without an octave are taken in the octave of the target note.
+    fn read_typed_answer(&mut self, round: &Round) -> anyhow::Result<Typed> {
+        let target = round.target();
+        let names = self.session.settings().notation.examples();
+        let (what, example) = match round.mode() {
+            Mode::Note => ("the note", names),
+            Mode::Interval => ("the top note (or both notes)", names),
+            Mode::Chord => ("the chord notes", names),
+            Mode::Melody => ("the melody notes", names),
+            Mode::Dynamics => ("the dynamics", "p or mf"),
+        };
+
+        loop {
+            println!(
+                "Type {}, e.g. {}, nothing to replay or ? for a hint:",
+                what, example
+            );
+            let line = self.read_line()?;
+            match line.trim() {
+                "" => return Ok(Typed::Replay),
+                "?" => return Ok(Typed::Hint),
+                _ => {}
+            }
+
+            if round.mode() == Mode::Dynamics {
+                match line.parse::<Dynamic>() {
+                    Ok(dynamic) => return Ok(Typed::Answer(vec![dynamic.velocity()])),
+                    Err(e) => {
+                        println!("{}", e);
+                        continue;
+                    }
+                }
+            }
+
+            let names = match self.session.settings().notation.parse_names(&line) {
+                Ok(names) => names,
+                Err(e) => {
+                    println!("{}", e);
+                    continue;
+                }
+            };
+            let count_ok = match round.mode() {
+                Mode::Interval => names.len() == 1 || names.len() == 2,
+                Mode::Chord => !names.is_empty(),
+                _ => names.len() == target.len(),
+            };
+            if !count_ok {
+                println!("Unexpected number of notes: {}", names.len());
+                continue;
+            }
+
+            let near = |i: usize| match round.mode() {
+                Mode::Interval if names.len() == 1 => target[1],
+                Mode::Chord => target[0],
+                _ => target[i],
+            };
+            match names
+                .iter()
+                .enumerate()
+                .map(|(i, name)| name.resolve(near(i)))
+                .collect()
+            {
+                Some(notes) => return Ok(Typed::Answer(notes)),
+                None => println!("Note is out of the MIDI range"),
+            }
+        }
+    }
+
+    /// Selects a program on the output, after the bank if one is set.
+    fn select_program(&mut self, program: u8) -> anyhow::Result<()> {
+        let settings = self.session.settings();
+        let channel = settings.output_channel;
+        let mut messages = Vec::new();
+        if let Some(bank) = settings.bank {
+            for &(controller, value) in &[(BANK_SELECT_MSB, bank >> 7), (BANK_SELECT_LSB, bank)] {
+                messages.push(Message::ControlChange {
+                    channel,
+                    controller,
+                    value: (value & 0x7f) as u8,
+                });
+            }
+        }
+        messages.push(Message::ProgramChange { channel, program });
+
+        for message in messages {
+            self.output.send(&message.to_bytes())?;
+        }
+        self.program = Some(program);
+        Ok(())
+    }
+
+    fn wait_ms(&mut self, ms: u64) -> anyhow::Result<()> {
+        self.output.wait(Duration::from_millis(ms))
+    }
+
+    fn note_on(&mut self, notes: &[u8], velocity: Velocity) -> anyhow::Result<()> {
+        let channel = self.session.settings().output_channel;
+        let mut rng = rand::thread_rng();
+        for &note in notes {
+            let message = Message::NoteOn {
+                channel,
+                note,
+                velocity: velocity.sample(&mut rng),
+            };
+            self.output.send(&message.to_bytes())?;
+        }
+        Ok(())
+    }
+
+    fn note_off(&mut self, notes: &[u8]) -> anyhow::Result<()> {
+        let channel = self.session.settings().output_channel;
+        for &note in notes {
+            let message = Message::NoteOff {
+                channel,
+                note,
+                velocity: VELOCITY,
+            };
+            self.output.send(&message.to_bytes())?;
+        }
+        Ok(())
+    }
+
+    fn send_pedal(&mut self, down: bool) -> anyhow::Result<()> {
+        let message = Message::ControlChange {
+            channel: self.session.settings().output_channel,
+            controller: SUSTAIN_PEDAL,
+            value: if down { 127 } else { 0 },
+        };
+        self.output.send(&message.to_bytes())
+    }
+
+    /// Releases the notes, or leaves them sounding until `release_sustained`
+    /// with `sustain`.
+    fn release(&mut self, notes: &[u8], sustain: bool) -> anyhow::Result<()> {
+        if sustain {
+            self.sustained.extend(notes);
+            Ok(())
+        } else {
+            self.note_off(notes)
+        }
+    }
+
+    fn release_sustained(&mut self) -> anyhow::Result<()> {
+        let notes = std::mem::take(&mut self.sustained);
+        self.note_off(&notes)
+    }
+
+    /// Plays the notes together or one after another with the configured
+    /// articulation.
+    fn play(
+        &mut self,
+        notes: &[u8],
+        simultaneous: bool,
+        duration_ms: u64,
+        velocity: Velocity,
+        sustain: bool,
+    ) -> anyhow::Result<()> {
+        let articulation = self.session.settings().articulation;
+        let held_ms = match articulation {
+            Articulation::Staccato => duration_ms / 2,
+            _ => duration_ms,
+        };
+
+        if articulation == Articulation::Pedal {
+            self.send_pedal(true)?;
+        }
+        if simultaneous {
+            self.note_on(notes, velocity)?;
+            self.wait_ms(held_ms)?;
+            self.release(notes, sustain)?;
+            self.wait_ms(duration_ms - held_ms)?;
+        } else if articulation == Articulation::Legato {
+            for (i, &note) in notes.iter().enumerate() {
+                let previous = if i > 0 { Some(notes[i - 1]) } else { None };
+                // A repeated note cannot overlap itself, its note off would
+                // cut the new one short.
+                if previous == Some(note) {
+                    self.release(&[note], sustain)?;
+                }
+                self.note_on(&[note], velocity)?;
+                if let Some(previous) = previous.filter(|&x| x != note) {
+                    self.release(&[previous], sustain)?;
+                }
+                self.wait_ms(duration_ms)?;
+            }
+            self.release(&notes[notes.len().saturating_sub(1)..], sustain)?;
+        } else {
+            for &note in notes {
+                self.note_on(&[note], velocity)?;
+                self.wait_ms(held_ms)?;
+                self.release(&[note], sustain)?;
+                self.wait_ms(duration_ms - held_ms)?;
+            }
+        }
+        if articulation == Articulation::Pedal {
+            self.send_pedal(false)?;
+        }
+        Ok(())
+    }
+
+    /// Plays the reference note, if any, and then the target, at the level
+    /// to guess in dynamics mode.
+    fn play_target(&mut self, round: &Round) -> anyhow::Result<()> {
+        let settings = self.session.settings();
+        let simultaneous = round.is_simultaneous(settings.harmonic);
+        let duration_ms = settings.guess_play_duration_ms;
+        let sustain = settings.sustain_until_answered;
+        let velocity = settings.velocity;
+        let target_velocity = round
+            .dynamic()
+            .map_or(velocity, |x| Velocity::Fixed(x.velocity()));
+        self.release_sustained()?;
+        if let Some(reference) = round.reference() {
+            self.play(&[reference], false, duration_ms, velocity, false)?;
+            self.wait_ms(duration_ms)?;
+        }
+        self.play(
+            round.target(),
+            simultaneous,
+            duration_ms,
+            target_velocity,
+            sustain,
+        )?;
+        // Whatever a microphone heard meanwhile is the target itself.
+        if self.input.hears_output() {
+            self.input.pending();
+        }
+        Ok(())
+    }
+
+    fn capture_answer(&mut self, round: &Round) -> anyhow::Result<Vec<u8>> {
+        match round.mode() {
+            Mode::Chord => self.capture_chord(),
+            Mode::Interval if self.session.settings().answer_both => self.capture_notes(2),
+            Mode::Melody => self.capture_notes(round.target().len()),
+            Mode::Dynamics => {
+                let presses = self.capture_presses(1)?;
+                Ok(presses.iter().map(|&(_, velocity)| velocity).collect())
+            }
+            _ => self.capture_notes(1),
+        }
+    }
+
+    fn capture_notes(&mut self, count: usize) -> anyhow::Result<Vec<u8>> {
+        let presses = self.capture_presses(count)?;
+        Ok(presses.iter().map(|&(note, _)| note).collect())
+    }
+
+    /// Notes and velocities of the next key presses.
+    fn capture_presses(&mut self, count: usize) -> anyhow::Result<Vec<(u8, u8)>> {
+        let mut presses: Vec<(u8, u8)> = self
+            .input
+            .pending()
+            .into_iter()
+            .filter_map(|event| match event {
+                KeyEvent::Press(note, velocity) => Some((note, velocity)),
+                KeyEvent::Release(_) => None,
+            })
+            .collect();
+        presses.drain(..presses.len().saturating_sub(count));
+        while presses.len() < count {
+            if let Some(KeyEvent::Press(note, velocity)) = self.input.recv(None)? {
+                presses.push((note, velocity));
+                self.release_sustained()?;
+            }
+        }
+        Ok(presses)
+    }
+
+    fn capture_chord(&mut self) -> anyhow::Result<Vec<u8>> {
+        self.input.pending();
+
+        let mut notes = Vec::new();
+        let mut held = HashSet::new();
+        let deadline = loop {
+            if let Some(KeyEvent::Press(x, _)) = self.input.recv(None)? {
+                self.release_sustained()?;
+                notes.push(x);
+                held.insert(x);
+                let window = Duration::from_millis(self.session.settings().chord_window_ms);
+                break Instant::now() + window;
+            }
+        };
+
+        while !held.is_empty() {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            match self.input.recv(Some(timeout))? {
+                Some(KeyEvent::Press(x, _)) => {
+                    if !notes.contains(&x) {
+                        notes.push(x);
+                    }
+                    held.insert(x);
+                }
+                Some(KeyEvent::Release(x)) => {
+                    held.remove(&x);
+                }
+                None => break,
+            }
+        }
+
+        notes.sort_unstable();
+        Ok(notes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{RecordingOutput, ScriptedInput, NOTE_OFF, NOTE_ON};
+    use crate::midi::Channel;
+    use crate::playback::Velocity;
+    use crate::session::{Reference, Settings};
+    use KeyEvent::{Press, Release};
+
+    fn settings(mode: Mode, min_note: u8, max_note: u8) -> Settings {
+        Settings {
+            mode,
+            min_note,
+            max_note,
+            guess_play_duration_ms: 0,
+            ..Settings::default()
+        }
+    }
+
+    #[test]
+    fn note_confirmed_after_replay() {
+        let mut input =
+            ScriptedInput::new(vec![Press(61, VELOCITY), Release(61), Press(60, VELOCITY)]);
+        let mut output = RecordingOutput::default();
+        let session = Session::new(settings(Mode::Note, 60, 60)).unwrap();
+        let mut game = Game::new(session, &mut input, &mut output, &b"n\ny\n"[..]);
+
+        assert!(game.play_round().unwrap().is_correct());
+        assert_eq!(
+            output.messages,
+            vec![
+                vec![NOTE_ON, 60, VELOCITY],
+                vec![NOTE_OFF, 60, VELOCITY],
+                vec![NOTE_ON, 60, VELOCITY],
+                vec![NOTE_OFF, 60, VELOCITY],
+            ]
+        );
+    }
+
+    /// Microphone that heard a note while the target was played.
+    struct Microphone {
+        heard: Vec<KeyEvent>,
+        played: ScriptedInput,
+    }
+
+    impl Input for Microphone {
+        fn recv(&mut self, timeout: Option<Duration>) -> anyhow::Result<Option<KeyEvent>> {
+            self.played.recv(timeout)
+        }
+
+        fn pending(&mut self) -> Vec<KeyEvent> {
+            std::mem::take(&mut self.heard)
+        }
+
+        fn hears_output(&self) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn heard_target_is_not_an_answer() {
+        let input = Microphone {
+            heard: vec![Press(60, VELOCITY)],
+            played: ScriptedInput::new(vec![Press(62, VELOCITY)]),
+        };
+        let settings = Settings {
+            non_interactive: true,
+            ..settings(Mode::Note, 60, 60)
+        };
+        let session = Session::new(settings).unwrap();
+        let mut game = Game::new(session, input, RecordingOutput::default(), &b""[..]);
+
+        assert_eq!(game.play_round().unwrap().answer, vec![62]);
+    }
+
+    #[test]
+    fn wrong_note_non_interactive() {
+        let input = ScriptedInput::new(vec![Press(62, VELOCITY)]);
+        let settings = Settings {
+            non_interactive: true,
+            ..settings(Mode::Note, 60, 60)
+        };
+        let session = Session::new(settings).unwrap();
+        let mut game = Game::new(session, input, RecordingOutput::default(), &b""[..]);
+
+        assert!(!game.play_round().unwrap().is_correct());
+        assert!(game.play_round().is_err());
+    }
+
+    #[test]
+    fn harmonic_interval_top_note_first() {
+        let input = ScriptedInput::new(vec![Press(64, VELOCITY), Press(60, VELOCITY)]);
+        let settings = Settings {
+            scale: "0,4".parse().unwrap(),
+            harmonic: true,
+            answer_both: true,
+            ..settings(Mode::Interval, 60, 64)
+        };
+        let session = Session::new(settings).unwrap();
+        let mut game = Game::new(session, input, RecordingOutput::default(), &b"y\n"[..]);
+
+        let result = game.play_round().unwrap();
+        assert!(result.is_correct(), "{}", result);
+    }
+
+    #[test]
+    fn interval_top_note() {
+        let input = ScriptedInput::new(vec![Press(64, VELOCITY)]);
+        let settings = Settings {
+            scale: "0,4".parse().unwrap(),
+            ..settings(Mode::Interval, 60, 64)
+        };
+        let session = Session::new(settings).unwrap();
+        let mut game = Game::new(session, input, RecordingOutput::default(), &b"y\n"[..]);
+
+        assert!(game.play_round().unwrap().is_correct());
+    }
+
+    #[test]
+    fn chord_missing_tone() {
+        let settings = Settings {
+            non_interactive: true,
+            scale: "major".parse().unwrap(),
+            ..settings(Mode::Chord, 60, 67)
+        };
+        let input = ScriptedInput::new(vec![
+            Press(60, VELOCITY),
+            Press(64, VELOCITY),
+            Press(67, VELOCITY),
+            Release(60),
+            Release(64),
+            Release(67),
+            Press(60, VELOCITY),
+            Press(64, VELOCITY),
+            Release(64),
+            Release(60),
+        ]);
+        let session = Session::new(settings).unwrap();
+        let mut game = Game::new(session, input, RecordingOutput::default(), &b""[..]);
+
+        assert!(game.play_round().unwrap().is_correct());
+        assert!(!game.play_round().unwrap().is_correct());
+    }
+
+    #[test]
+    fn typed_answer_after_replay() {
+        let mut output = RecordingOutput::default();
+        let settings = Settings {
+            typed_answers: true,
+            ..settings(Mode::Note, 61, 61)
+        };
+        let session = Session::new(settings).unwrap();
+        let prompt = &b"\nX\nDb\nc#5\n"[..];
+        let mut game = Game::new(session, ScriptedInput::new(None), &mut output, prompt);
+
+        assert!(game.play_round().unwrap().is_correct());
+        assert!(!game.play_round().unwrap().is_correct());
+        assert_eq!(output.messages.len(), 6);
+    }
+
+    #[test]
+    fn dynamics_answered_with_velocity() {
+        let mut output = RecordingOutput::default();
+        let settings = Settings {
+            non_interactive: true,
+            ..settings(Mode::Dynamics, 60, 72)
+        };
+        let session = Session::new(settings).unwrap();
+        let input = ScriptedInput::new(vec![Press(64, 84)]);
+        let mut game = Game::new(session, input, &mut output, &b""[..]);
+
+        let result = game.play_round().unwrap();
+        let velocity = output.messages[0][2];
+        assert_eq!(output.messages[0][1], 66);
+        assert_eq!(result.target, vec![66]);
+        assert!(result.answer.is_empty());
+        assert_eq!(
+            result.is_correct(),
+            velocity == Dynamic::MEZZO_FORTE.velocity()
+        );
+    }
+
+    #[test]
+    fn hint_lowers_score() {
+        // The answer is kept after the hint, so no other press is needed.
+        let input = ScriptedInput::new(vec![Press(61, VELOCITY)]);
+        let session = Session::new(settings(Mode::Note, 61, 61)).unwrap();
+        let mut game = Game::new(session, input, RecordingOutput::default(), &b"?\ny\n"[..]);
+
+        let result = game.play_round().unwrap();
+        assert_eq!((result.hints, result.score), (1, 0.75));
+    }
+
+    #[test]
+    fn reference_before_target() {
+        let mut output = RecordingOutput::default();
+        let settings = Settings {
+            non_interactive: true,
+            reference: Some(Reference::Note(69)),
+            ..settings(Mode::Note, 60, 60)
+        };
+        let session = Session::new(settings).unwrap();
+        let input = ScriptedInput::new(vec![Press(60, VELOCITY)]);
+        let mut game = Game::new(session, input, &mut output, &b""[..]);
+
+        assert!(game.play_round().unwrap().is_correct());
+        assert_eq!(output.messages[0], vec![NOTE_ON, 69, VELOCITY]);
+        assert_eq!(output.messages[2], vec![NOTE_ON, 60, VELOCITY]);
+    }
+
+    fn play_melody(settings: Settings) -> (Vec<u8>, Vec<Vec<u8>>) {
+        let mut output = RecordingOutput::default();
+        let settings = Settings {
+            non_interactive: true,
+            melody_length: 2,
+            ..settings
+        };
+        let session = Session::new(settings).unwrap();
+        let input = ScriptedInput::new(vec![Press(60, VELOCITY), Press(60, VELOCITY)]);
+        let mut game = Game::new(session, input, &mut output, &b""[..]);
+        let target = game.play_round().unwrap().target;
+        (target, output.messages)
+    }
+
+    #[test]
+    fn legato_with_pedal() {
+        // Repeated notes are played differently, see `legato_repeated_note`.
+        let (target, messages) = loop {
+            let (target, messages) = play_melody(Settings {
+                articulation: Articulation::Legato,
+                ..settings(Mode::Melody, 60, 72)
+            });
+            if target[0] != target[1] {
+                break (target, messages);
+            }
+        };
+        assert_eq!(
+            messages,
+            vec![
+                vec![NOTE_ON, target[0], VELOCITY],
+                vec![NOTE_ON, target[1], VELOCITY],
+                vec![NOTE_OFF, target[0], VELOCITY],
+                vec![NOTE_OFF, target[1], VELOCITY],
+            ]
+        );
+
+        let (_, messages) = play_melody(Settings {
+            articulation: Articulation::Pedal,
+            ..settings(Mode::Melody, 60, 72)
+        });
+        assert_eq!(messages[0], vec![0xb0, SUSTAIN_PEDAL, 127]);
+        assert_eq!(messages[5], vec![0xb0, SUSTAIN_PEDAL, 0]);
+    }
+
+    #[test]
+    fn legato_repeated_note() {
+        let (_, messages) = play_melody(Settings {
+            articulation: Articulation::Legato,
+            ..settings(Mode::Melody, 60, 60)
+        });
+        assert_eq!(
+            messages,
+            vec![
+                vec![NOTE_ON, 60, VELOCITY],
+                vec![NOTE_OFF, 60, VELOCITY],
+                vec![NOTE_ON, 60, VELOCITY],
+                vec![NOTE_OFF, 60, VELOCITY],
+            ]
+        );
+    }
+
+    #[test]
+    fn sustain_until_answered() {
+        let (target, messages) = play_melody(Settings {
+            velocity: Velocity::Fixed(100),
+            sustain_until_answered: true,
+            ..settings(Mode::Melody, 60, 72)
+        });
+        assert_eq!(
+            messages,
+            vec![
+                vec![NOTE_ON, target[0], 100],
+                vec![NOTE_ON, target[1], 100],
+                vec![NOTE_OFF, target[0], VELOCITY],
+                vec![NOTE_OFF, target[1], VELOCITY],
+            ]
+        );
+    }
+
+    #[test]
+    fn notes_on_output_channel() {
+        let mut output = RecordingOutput::default();
+        let settings = Settings {
+            non_interactive: true,
+            output_channel: Channel(9),
+            ..settings(Mode::Note, 60, 60)
+        };
+        let session = Session::new(settings).unwrap();
+        let input = ScriptedInput::new(vec![Press(60, VELOCITY)]);
+        let mut game = Game::new(session, input, &mut output, &b""[..]);
+
+        game.play_round().unwrap();
+        assert_eq!(
+            output.messages,
+            vec![vec![0x99, 60, VELOCITY], vec![0x89, 60, VELOCITY]]
+        );
+    }
+
+    #[test]
+    fn instrument_is_selected_once() {
+        let mut output = RecordingOutput::default();
+        let settings = Settings {
+            non_interactive: true,
+            instrument: Some(Instrument::Program(73)),
+            bank: Some(130),
+            ..settings(Mode::Note, 60, 60)
+        };
+        let session = Session::new(settings).unwrap();
+        let input = ScriptedInput::new(vec![Press(60, VELOCITY), Press(60, VELOCITY)]);
+        let mut game = Game::new(session, input, &mut output, &b""[..]);
+
+        game.play_round().unwrap();
+        game.play_round().unwrap();
+        assert_eq!(
+            output.messages[..4],
+            [
+                vec![0xb0, BANK_SELECT_MSB, 1],
+                vec![0xb0, BANK_SELECT_LSB, 2],
+                vec![0xc0, 73],
+                vec![NOTE_ON, 60, VELOCITY],
+            ]
+        );
+        assert_eq!(output.messages.len(), 7);
+    }
+
+    #[test]
+    fn run_stops_after_rounds() {
+        let input = ScriptedInput::new(vec![
+            Press(60, VELOCITY),
+            Press(61, VELOCITY),
+            Press(60, VELOCITY),
+        ]);
+        let settings = Settings {
+            non_interactive: true,
+            rounds: Some(2),
+            ..settings(Mode::Note, 60, 60)
+        };
+        let session = Session::new(settings).unwrap();
+        let mut game = Game::new(session, input, RecordingOutput::default(), &b""[..]);
+
+        game.run().unwrap();
+        let summary = game.summary();
+        let summary = summary.lock().unwrap();
+        assert_eq!((summary.rounds, summary.correct), (2, 1));
+        assert_eq!(summary.confusion[0][1], 1);
+    }
+}
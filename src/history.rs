@@ -0,0 +1,396 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Context;
+
+use crate::note::{pitch_classes, Notation, Note, PitchClass, SIGN_COUNT};
+use crate::session::{unanswered_notes, Mode, RoundResult};
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Single played round as stored in the history file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Record {
+    /// Seconds since the Unix epoch.
+    pub timestamp: u64,
+    pub mode: Mode,
+    pub target: Vec<u8>,
+    pub answer: Vec<u8>,
+    pub score: f32,
+    pub replays: u32,
+    pub response_time_ms: u64,
+}
+
+fn notes_to_field(notes: &[u8]) -> String {
+    notes
+        .iter()
+        .map(|x| x.to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn field_to_notes(field: &str) -> anyhow::Result<Vec<u8>> {
+    field
+        .split_whitespace()
+        .map(|x| x.parse().context("invalid note number"))
+        .collect()
+}
+
+impl Record {
+    pub fn new(result: &RoundResult, replays: u32, response_time_ms: u64) -> Self {
+        Record {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |x| x.as_secs()),
+            mode: result.mode,
+            target: result.target.clone(),
+            answer: result.answer.clone(),
+            score: result.score,
+            replays,
+            response_time_ms,
+        }
+    }
+
+    fn parse(line: &str) -> anyhow::Result<Self> {
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() != 7 {
+            anyhow::bail!("expected 7 fields, got {}", fields.len());
+        }
+
+        Ok(Record {
+            timestamp: fields[0].parse().context("invalid timestamp")?,
+            mode: fields[1].parse().map_err(anyhow::Error::msg)?,
+            target: field_to_notes(fields[2])?,
+            answer: field_to_notes(fields[3])?,
+            score: fields[4].parse().context("invalid score")?,
+            replays: fields[5].parse().context("invalid replay count")?,
+            response_time_ms: fields[6].parse().context("invalid response time")?,
+        })
+    }
+
+    /// Every target note along with whether it was answered right.
+    ///
+    /// Chord notes are compared by pitch class, other modes compare notes
+    /// at the same position. The reference of an interval answered with its
+    /// top note alone is left out, and dynamics rounds answer no notes.
+    pub fn note_results(&self) -> Vec<(u8, bool)> {
+        match self.mode {
+            Mode::Dynamics => Vec::new(),
+            Mode::Chord => {
+                let answer = pitch_classes(&self.answer);
+                self.target
+                    .iter()
+                    .map(|&x| (x, answer.contains(&(x % SIGN_COUNT as u8))))
+                    .collect()
+            }
+            _ => self
+                .target
+                .iter()
+                .skip(unanswered_notes(self.mode, &self.answer))
+                .enumerate()
+                .map(|(i, &x)| (x, self.answer.get(i) == Some(&x)))
+                .collect(),
+        }
+    }
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+            self.timestamp,
+            self.mode,
+            notes_to_field(&self.target),
+            notes_to_field(&self.answer),
+            self.score,
+            self.replays,
+            self.response_time_ms
+        )
+    }
+}
+
+/// Append-only file of played rounds, one tab-separated record per line.
+pub struct History {
+    path: PathBuf,
+}
+
+impl History {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        History { path: path.into() }
+    }
+
+    /// `guess-note/history.tsv` in the user data directory.
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::data_dir().map(|x| x.join("guess-note").join("history.tsv"))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn append(&self, record: &Record) -> anyhow::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("cannot open history file {}", self.path.display()))?;
+        writeln!(file, "{}", record)?;
+        Ok(())
+    }
+
+    /// Reads every record, a missing file is an empty history.
+    pub fn load(&self) -> anyhow::Result<Vec<Record>> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        BufReader::new(file)
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !matches!(line, Ok(x) if x.trim().is_empty()))
+            .map(|(i, line)| {
+                Record::parse(&line?)
+                    .with_context(|| format!("invalid record at {}:{}", self.path.display(), i + 1))
+            })
+            .collect()
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+pub struct Accuracy {
+    pub correct: u32,
+    pub total: u32,
+    /// Sum of scores, counting partially right answers.
+    pub credit: f32,
+}
+
+impl Accuracy {
+    pub(crate) fn add(&mut self, correct: bool) {
+        self.add_score(if correct { 1.0 } else { 0.0 });
+    }
+
+    pub(crate) fn add_score(&mut self, score: f32) {
+        self.total += 1;
+        self.credit += score;
+        if score >= 1.0 {
+            self.correct += 1;
+        }
+    }
+
+    fn merge(&mut self, other: &Accuracy) {
+        self.correct += other.correct;
+        self.total += other.total;
+        self.credit += other.credit;
+    }
+
+    pub fn ratio(&self) -> f32 {
+        if self.total == 0 {
+            0.0
+        } else {
+            self.correct as f32 / self.total as f32
+        }
+    }
+}
+
+impl fmt::Display for Accuracy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:>5}/{:<5} {:>5.1}%",
+            self.correct,
+            self.total,
+            self.ratio() * 100.0
+        )?;
+        if self.credit > self.correct as f32 {
+            write!(
+                f,
+                " ({:.1}% with partial credit)",
+                self.credit / self.total as f32 * 100.0
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Accuracy of recorded rounds broken down in several ways.
+#[derive(Default)]
+pub struct Stats {
+    pub rounds: Accuracy,
+    pub by_mode: BTreeMap<String, Accuracy>,
+    pub by_note: BTreeMap<u8, Accuracy>,
+    pub by_pitch_class: BTreeMap<u8, Accuracy>,
+    /// Rounds per day, keyed by days since the Unix epoch.
+    pub by_day: BTreeMap<u64, Accuracy>,
+    pub notation: Notation,
+}
+
+impl Stats {
+    pub fn new(records: &[Record]) -> Self {
+        let mut stats = Stats::default();
+        for record in records {
+            stats.rounds.add_score(record.score);
+            stats
+                .by_mode
+                .entry(record.mode.to_string())
+                .or_default()
+                .add_score(record.score);
+            stats
+                .by_day
+                .entry(record.timestamp / SECONDS_PER_DAY)
+                .or_default()
+                .add_score(record.score);
+
+            for (note, correct) in record.note_results() {
+                stats.by_note.entry(note).or_default().add(correct);
+                stats
+                    .by_pitch_class
+                    .entry(note % SIGN_COUNT as u8)
+                    .or_default()
+                    .add(correct);
+            }
+        }
+        stats
+    }
+
+    /// Accuracy per octave, numbered like the printed note names.
+    pub fn by_octave(&self) -> BTreeMap<i16, Accuracy> {
+        let mut by_octave = BTreeMap::new();
+        for (&note, accuracy) in &self.by_note {
+            by_octave
+                .entry(self.notation.octave(Note(note)))
+                .or_insert_with(Accuracy::default)
+                .merge(accuracy);
+        }
+        by_octave
+    }
+}
+
+/// Formats days since the Unix epoch as `YYYY-MM-DD`.
+fn format_day(days: u64) -> String {
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Rounds       {}", self.rounds)?;
+
+        writeln!(f, "\nBy mode:")?;
+        for (mode, accuracy) in &self.by_mode {
+            writeln!(f, "  {:<10} {}", mode, accuracy)?;
+        }
+
+        writeln!(f, "\nBy pitch class:")?;
+        for (&pitch_class, accuracy) in &self.by_pitch_class {
+            writeln!(
+                f,
+                "  {:<10} {}",
+                self.notation.pitch_class(PitchClass(pitch_class)),
+                accuracy
+            )?;
+        }
+
+        writeln!(f, "\nBy octave:")?;
+        for (octave, accuracy) in &self.by_octave() {
+            writeln!(f, "  {:<10} {}", octave, accuracy)?;
+        }
+
+        writeln!(f, "\nBy note:")?;
+        for (&note, accuracy) in &self.by_note {
+            writeln!(f, "  {:<10} {}", self.notation.note(Note(note)), accuracy)?;
+        }
+
+        write!(f, "\nBy day:")?;
+        for (&day, accuracy) in &self.by_day {
+            write!(f, "\n  {:<10} {}", format_day(day), accuracy)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session::{Session, Settings};
+
+    #[test]
+    fn record_roundtrip() {
+        let record = Record {
+            timestamp: 1_600_000_000,
+            mode: Mode::Interval,
+            target: vec![60, 64],
+            answer: vec![60, 63],
+            score: 0.0,
+            replays: 2,
+            response_time_ms: 1500,
+        };
+
+        assert_eq!(Record::parse(&record.to_string()).unwrap(), record);
+    }
+
+    #[test]
+    fn unanswered_reference_is_not_counted() {
+        let session = Session::new(Settings {
+            mode: Mode::Interval,
+            ..Settings::default()
+        })
+        .unwrap();
+        let round = session.next_round();
+        let top = round.target()[1];
+
+        let record = Record::new(&round.submit(&[top]), 0, 0);
+        assert_eq!(record.answer, vec![top]);
+        assert_eq!(record.note_results(), vec![(top, true)]);
+    }
+
+    #[test]
+    fn octaves_follow_notation() {
+        let record = Record {
+            timestamp: 0,
+            mode: Mode::Melody,
+            target: vec![48, 60, 71],
+            answer: vec![48, 61, 71],
+            score: 0.0,
+            replays: 0,
+            response_time_ms: 0,
+        };
+        let stats = Stats {
+            notation: Notation {
+                middle_c_octave: 3,
+                ..Notation::default()
+            },
+            ..Stats::new(&[record])
+        };
+        let by_octave: Vec<(i16, u32, u32)> = stats
+            .by_octave()
+            .into_iter()
+            .map(|(octave, x)| (octave, x.correct, x.total))
+            .collect();
+        assert_eq!(by_octave, vec![(2, 1, 1), (3, 1, 2)]);
+    }
+
+    #[test]
+    fn days_are_formatted_as_dates() {
+        assert_eq!(format_day(0), "1970-01-01");
+        assert_eq!(format_day(1_600_000_000 / SECONDS_PER_DAY), "2020-09-13");
+    }
+}
@@ -0,0 +1,119 @@
+use std::str::FromStr;
+
+use rand::seq::SliceRandom;
+
+/// General MIDI programs known by name, counted from 0.
+const PROGRAMS: [(&str, u8); 26] = [
+    ("piano", 0),
+    ("electric-piano", 4),
+    ("harpsichord", 6),
+    ("celesta", 8),
+    ("glockenspiel", 9),
+    ("vibraphone", 11),
+    ("marimba", 12),
+    ("organ", 19),
+    ("accordion", 21),
+    ("guitar", 24),
+    ("electric-guitar", 27),
+    ("bass", 32),
+    ("violin", 40),
+    ("viola", 41),
+    ("cello", 42),
+    ("harp", 46),
+    ("strings", 48),
+    ("choir", 52),
+    ("trumpet", 56),
+    ("trombone", 57),
+    ("horn", 60),
+    ("sax", 65),
+    ("oboe", 68),
+    ("clarinet", 71),
+    ("flute", 73),
+    ("pan-flute", 75),
+];
+
+/// Name of a General MIDI program, or its number from 1 for unnamed ones.
+pub fn program_name(program: u8) -> String {
+    match PROGRAMS.iter().find(|&&(_, x)| x == program) {
+        Some((name, _)) => name.to_string(),
+        None => format!("program {}", program + 1),
+    }
+}
+
+/// Sound to select on the output before playing.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Instrument {
+    /// General MIDI program, counted from 0.
+    Program(u8),
+    /// A different named program every round.
+    Random,
+}
+
+impl Instrument {
+    /// Program to play the next round with, for `Random` a different one than
+    /// the `previous` program.
+    pub fn program(self, previous: Option<u8>) -> u8 {
+        match self {
+            Instrument::Program(program) => program,
+            Instrument::Random => {
+                let programs: Vec<u8> = PROGRAMS
+                    .iter()
+                    .map(|&(_, x)| x)
+                    .filter(|&x| Some(x) != previous)
+                    .collect();
+                *programs.choose(&mut rand::thread_rng()).unwrap()
+            }
+        }
+    }
+}
+
+impl FromStr for Instrument {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.to_lowercase();
+        if s == "random" {
+            return Ok(Instrument::Random);
+        }
+        if let Some(&(_, program)) = PROGRAMS.iter().find(|(x, _)| *x == s) {
+            return Ok(Instrument::Program(program));
+        }
+        match s.parse::<u8>() {
+            Ok(x @ 1..=128) => Ok(Instrument::Program(x - 1)),
+            _ => Err(format!(
+                "unknown instrument `{}`, expected a General MIDI program from 1 to 128, \
+                 random or one of: {}",
+                s,
+                PROGRAMS
+                    .iter()
+                    .map(|(x, _)| *x)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn instruments_by_name_or_number() {
+        assert_eq!("Flute".parse(), Ok(Instrument::Program(73)));
+        assert_eq!("1".parse(), Ok(Instrument::Program(0)));
+        assert_eq!("random".parse(), Ok(Instrument::Random));
+        assert!("0".parse::<Instrument>().is_err());
+        assert!("kazoo".parse::<Instrument>().is_err());
+        assert_eq!(program_name(40), "violin");
+        assert_eq!(program_name(80), "program 81");
+    }
+
+    #[test]
+    fn random_instrument_changes() {
+        assert_eq!(Instrument::Program(73).program(Some(73)), 73);
+        for _ in 0..100 {
+            assert_ne!(Instrument::Random.program(Some(0)), 0);
+        }
+    }
+}
@@ -0,0 +1,31 @@
+const OCTAVE: u8 = 12;
+const NAMES: [&str; OCTAVE as usize] = [
+    "unison",
+    "minor second",
+    "major second",
+    "minor third",
+    "major third",
+    "perfect fourth",
+    "tritone",
+    "perfect fifth",
+    "minor sixth",
+    "major sixth",
+    "minor seventh",
+    "major seventh",
+];
+
+/// Name of the interval between two notes, e.g. "minor third" or "descending octave".
+pub fn interval_name(from: u8, to: u8) -> String {
+    let distance = to.abs_diff(from);
+    let direction = if to < from { "descending " } else { "" };
+
+    let name = match (distance / OCTAVE, distance % OCTAVE) {
+        (0, x) => NAMES[usize::from(x)].to_string(),
+        (1, 0) => "octave".to_string(),
+        (1, x) => format!("octave + {}", NAMES[usize::from(x)]),
+        (n, 0) => format!("{} octaves", n),
+        (n, x) => format!("{} octaves + {}", n, NAMES[usize::from(x)]),
+    };
+
+    format!("{}{}", direction, name)
+}
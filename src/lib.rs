@@ -0,0 +1,26 @@
+//! Ear training by guessing notes, intervals, chords and melodies played over MIDI.
+//!
+//! A [`Session`] generates [`Round`]s and scores submitted answers, while [`Game`]
+//! drives a session interactively through an [`Input`] and an [`Output`].
+
+pub mod adaptive;
+pub mod backend;
+pub mod chord;
+pub mod config;
+pub mod game;
+pub mod history;
+pub mod instrument;
+pub mod interval;
+pub mod midi;
+pub mod note;
+pub mod pitch;
+pub mod playback;
+pub mod scale;
+pub mod session;
+pub mod soundfont;
+pub mod summary;
+pub mod synth;
+
+pub use backend::{Input, KeyEvent, Output};
+pub use game::Game;
+pub use session::{Mode, Reference, Round, RoundResult, Scoring, Session, Settings};
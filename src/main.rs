@@ -1,20 +1,130 @@
 use std::io;
-use std::sync::mpsc;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
 
 use anyhow::Context;
 use argh::FromArgs;
 use midir::{MidiInput, MidiOutput};
 
-const NOTE_ON: u8 = 0x90;
-const NOTE_OFF: u8 = 0x80;
-const VELOCITY: u8 = 0x40;
+use guess_note::adaptive::Adaptive;
+use guess_note::backend::{
+    find_port, port_names, Input, LastPorts, MidirInput, MidirOutput, NoInput, Output,
+    PortSelector, ScriptedInput,
+};
+use guess_note::config::Config;
+use guess_note::history::{History, Stats};
+use guess_note::instrument::Instrument;
+use guess_note::midi::{Channel, ChannelFilter, Message};
+use guess_note::note::{Accidentals, Naming, Notation};
+use guess_note::pitch::{read_wav, MicInput};
+use guess_note::playback::{Articulation, Velocity};
+use guess_note::scale::{Key, Scale};
+use guess_note::synth::{AudioOutput, Sound, WavOutput, Waveform};
+use guess_note::{Game, Mode, Reference, Scoring, Session, Settings};
+
+#[derive(Clone, Copy, PartialEq)]
+enum InputKind {
+    Midi,
+    Audio,
+    Text,
+}
+
+impl FromStr for InputKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "midi" => Ok(InputKind::Midi),
+            "audio" => Ok(InputKind::Audio),
+            "text" => Ok(InputKind::Text),
+            _ => Err(format!(
+                "unknown input `{}`, expected midi, audio or text",
+                s
+            )),
+        }
+    }
+}
+
+enum OutputKind {
+    Midi,
+    Synth,
+}
+
+impl FromStr for OutputKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "midi" => Ok(OutputKind::Midi),
+            "synth" => Ok(OutputKind::Synth),
+            _ => Err(format!("unknown output `{}`, expected midi or synth", s)),
+        }
+    }
+}
 
 #[derive(FromArgs)]
 /// Guess Note arguments
 struct Args {
     #[argh(option)]
-    /// MIDI input port number
-    port_no: Option<usize>,
+    /// file with default settings, guess-note/config.toml in the user config
+    /// directory by default
+    config: Option<PathBuf>,
+    #[argh(option)]
+    /// named profile of settings to use from the config file
+    profile: Option<String>,
+    #[argh(option)]
+    /// MIDI input port number or a part of its name
+    input_port: Option<PortSelector>,
+    #[argh(option)]
+    /// MIDI output port number or a part of its name
+    output_port: Option<PortSelector>,
+    #[argh(option, default = "ChannelFilter::Omni")]
+    /// MIDI channel to take notes from, 1 to 16 or omni (any channel)
+    input_channel: ChannelFilter,
+    #[argh(option, default = "Channel(0)")]
+    /// MIDI channel to play notes on, 1 to 16
+    output_channel: Channel,
+    #[argh(option)]
+    /// instrument to play with: a General MIDI program number from 1 to 128,
+    /// a name like piano, strings, flute or organ, or random (every round)
+    instrument: Option<Instrument>,
+    #[argh(option)]
+    /// bank to select before the instrument, from 0 to 16383
+    bank: Option<u16>,
+    #[argh(option, default = "Velocity::default()")]
+    /// how hard to play notes: a value from 1 to 127 like 80, a random range
+    /// like 40-100 or a humanized value like ~80
+    velocity: Velocity,
+    #[argh(option, default = "Articulation::Normal")]
+    /// how to connect notes: normal, staccato (short), legato (overlapping)
+    /// or pedal (held by the sustain pedal)
+    articulation: Articulation,
+    #[argh(switch)]
+    /// let the played notes ring until the answer is played
+    sustain_until_answered: bool,
+    #[argh(option, default = "InputKind::Midi")]
+    /// how to take answers: midi (from the input port), audio (singing or
+    /// playing into the default recording device) or text (typing note names)
+    input: InputKind,
+    #[argh(option)]
+    /// take answers from the notes detected in this WAV file instead
+    input_wav: Option<PathBuf>,
+    #[argh(option, default = "OutputKind::Midi")]
+    /// how to play notes: midi (to the output port) or synth (built-in
+    /// synthesizer)
+    output: OutputKind,
+    #[argh(option, default = "Waveform::Piano")]
+    /// built-in synthesizer sound: sine, triangle or piano
+    waveform: Waveform,
+    #[argh(option)]
+    /// SF2 file for the built-in synthesizer to play instead of a waveform,
+    /// implies --output synth
+    soundfont: Option<PathBuf>,
+    #[argh(option)]
+    /// render the built-in synthesizer to this WAV file instead of the sound
+    /// card, implies --output synth
+    wav: Option<PathBuf>,
     #[argh(switch, short = 'n')]
     /// wether or not ask for any cli input
     non_interactive: bool,
@@ -27,23 +137,122 @@ struct Args {
     #[argh(option, default = "150")]
     /// how long to play guessed note
     guess_play_duration_ms: u64,
+    #[argh(option, default = "Mode::Note")]
+    /// what to guess: note, interval, chord, melody or dynamics
+    mode: Mode,
+    #[argh(option, default = "12")]
+    /// largest interval (or melody leap) to generate in semitones
+    max_interval: u8,
+    #[argh(switch)]
+    /// play both interval notes at once instead of one after another
+    harmonic: bool,
+    #[argh(switch)]
+    /// answer an interval with both notes instead of only the top one
+    answer_both: bool,
+    #[argh(switch)]
+    /// generate seventh chords along with triads
+    sevenths: bool,
+    #[argh(option, default = "2000")]
+    /// how long to collect chord notes after the first key press
+    chord_window_ms: u64,
+    #[argh(option, default = "4")]
+    /// number of notes in a melody
+    melody_length: usize,
+    #[argh(option, default = "Key(0)")]
+    /// tonic of the key, e.g. C, F# or Bb
+    key: Key,
+    #[argh(option, default = "Scale::default()")]
+    /// scale to generate notes from, e.g. major, minor, dorian, pentatonic,
+    /// chromatic or comma-separated semitones from the tonic
+    scale: Scale,
+    #[argh(option, default = "Scoring::Exact")]
+    /// how to score notes in the wrong octave: exact (wrong), partial (half
+    /// credit) or pitch-class (right)
+    scoring: Scoring,
+    #[argh(option, default = "Naming::English")]
+    /// note names to print and type: english, german (H and B), fixed-do or
+    /// movable-do
+    naming: Naming,
+    #[argh(option, default = "Accidentals::Key")]
+    /// how to spell black keys: key (as in the key signature), sharps or flats
+    accidentals: Accidentals,
+    #[argh(option, default = "4")]
+    /// octave number of middle C, e.g. 4 (C4) or 3 (C3)
+    middle_c_octave: i16,
+    #[argh(option)]
+    /// note to play before each round to guess relative to: a note like A4,
+    /// tonic (of the key) or random
+    reference: Option<String>,
+    #[argh(switch)]
+    /// play a cadence establishing the key before each round
+    cadence: bool,
+    #[argh(option, default = "600")]
+    /// how long to play each cadence chord
+    cadence_chord_ms: u64,
+    #[argh(option)]
+    /// stop after this many rounds
+    rounds: Option<u32>,
+    #[argh(option)]
+    /// stop after the round running when this many seconds pass
+    time_limit_s: Option<u64>,
+    #[argh(switch)]
+    /// drill notes and intervals with more mistakes in the history more often
+    /// and widen the note range as accuracy improves
+    adaptive: bool,
+    #[argh(option)]
+    /// file to save played rounds to, guess-note/history.tsv in the user data
+    /// directory by default
+    history: Option<PathBuf>,
+    #[argh(switch)]
+    /// do not save played rounds
+    no_history: bool,
+    #[argh(subcommand)]
+    command: Option<Command>,
 }
 
-fn note_number_to_sign(x: u8) -> String {
-    const SIGN_COUNT: usize = 12;
-    const SIGNS: [&str; SIGN_COUNT] = [
-        "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
-    ];
-
-    format!(
-        "{:>2}{}",
-        SIGNS[usize::from(x) % SIGN_COUNT],
-        i16::from(x) / SIGN_COUNT as i16 - 1
-    )
+#[derive(FromArgs)]
+#[argh(subcommand)]
+enum Command {
+    Stats(StatsArgs),
 }
 
-fn sleep_ms(ms: u64) {
-    std::thread::sleep(std::time::Duration::from_millis(ms))
+#[derive(FromArgs)]
+/// Show accuracy of saved rounds
+#[argh(subcommand, name = "stats")]
+struct StatsArgs {}
+
+/// Parses the command line along with the settings from the config file,
+/// which the command line overrides.
+fn parse_args() -> anyhow::Result<Args> {
+    let given: Vec<String> = std::env::args().skip(1).collect();
+    // Switches of the file may be negated, which only parses once the file is
+    // known, so look for it without them first.
+    let first: Vec<&str> = given
+        .iter()
+        .map(String::as_str)
+        .filter(|x| !x.starts_with("--no-"))
+        .collect();
+    let args = match Args::from_args(&["guess-note"], &first) {
+        Ok(args) => args,
+        Err(_) => return Ok(argh::from_env()),
+    };
+    let path = match args.config.clone().or_else(Config::default_path) {
+        Some(path) if path.exists() || args.config.is_some() || args.profile.is_some() => path,
+        _ => return Ok(argh::from_env()),
+    };
+
+    let config = Config::load(&path)?;
+    let profile = args.profile.as_deref();
+    let mut combined = config.args(profile, &given)?;
+    combined.extend(config.without_negations(profile, &given)?);
+    let combined: Vec<&str> = combined.iter().map(String::as_str).collect();
+    Args::from_args(&["guess-note"], &combined).map_err(|e| {
+        anyhow::anyhow!(
+            "Invalid settings in {}: {}",
+            path.display(),
+            e.output.trim()
+        )
+    })
 }
 
 fn main() -> anyhow::Result<()> {
@@ -58,114 +267,208 @@ fn main() -> anyhow::Result<()> {
         }};
     }
 
-    let args = argh::from_env::<Args>();
+    let args = parse_args()?;
 
-    if args.min_note > args.max_note {
-        anyhow::bail!("Note range cannot be empty");
-    }
-
-    let midi_in = MidiInput::new("guess-note-input")?;
-    let midi_out = MidiOutput::new("guess-note-output")?;
+    let history = if args.no_history {
+        None
+    } else {
+        args.history
+            .clone()
+            .or_else(History::default_path)
+            .map(History::new)
+    };
 
-    let out_ports = midi_out.ports();
-    let mut in_ports = midi_in.ports();
+    let notation = Notation {
+        naming: args.naming,
+        accidentals: args.accidentals,
+        tonic: args.key.0,
+        minor: args.scale.is_minor(),
+        middle_c_octave: args.middle_c_octave,
+    };
 
-    let port_no = if let Some(port_no) = args.port_no {
-        port_no
-    } else {
-        if out_ports.is_empty() {
-            anyhow::bail!("No available MIDI ports found");
+    if let Some(Command::Stats(_)) = args.command {
+        let history = history.context("No history file to read")?;
+        let records = history.load()?;
+        if records.is_empty() {
+            println!("No rounds saved in {} yet", history.path().display());
+        } else {
+            let stats = Stats {
+                notation,
+                ..Stats::new(&records)
+            };
+            println!("{}", stats);
         }
+        return Ok(());
+    }
 
-        println!("Select input port:");
-        for (i, p) in out_ports.iter().enumerate() {
-            println!("{}: {}", i, midi_out.port_name(p).unwrap());
-        }
+    let output_kind = if args.soundfont.is_some() || args.wav.is_some() {
+        OutputKind::Synth
+    } else {
+        args.output
+    };
+    let midi_input = args.input_wav.is_none() && args.input == InputKind::Midi;
 
-        read_line!()
-            .trim()
-            .parse()
-            .context("invalid input, must be a number")?
+    // MIDI is only initialized for the sides using it, so that the other
+    // inputs and outputs work without MIDI support.
+    let ports_path = if midi_input || matches!(output_kind, OutputKind::Midi) {
+        LastPorts::default_path()
+    } else {
+        None
     };
-    let out_port = &out_ports[port_no];
-    let in_port = &mut in_ports[port_no];
-
-    let (tx, rx) = mpsc::channel();
-
-    let _conn_in = midi_in.connect(
-        in_port,
-        "guess-note-input",
-        move |_, message, _| {
-            match message {
-                &[x, _, z] if (x == NOTE_ON || x == NOTE_OFF) && z != 0 => {}
-                _ => return,
-            }
-            let _ = tx.send(message[1]);
-        },
-        (),
-    );
-    let mut conn_out = midi_out.connect(out_port, "midir-test").unwrap();
-
-    loop {
-        println!("\n ~~ Guess the note! ~~");
-
-        let guess_note =
-            rand::random::<f32>() * (args.max_note - args.min_note) as f32 + args.min_note as f32;
-        let guess_note = guess_note as u8;
-
-        macro_rules! capture_next_note {
-            () => {
-                if let Some(x) = rx.try_iter().last() {
-                    x
-                } else {
-                    rx.recv()?
+    let saved_ports = match &ports_path {
+        Some(path) => LastPorts::load(path)?,
+        None => LastPorts::default(),
+    };
+    let mut last_ports = saved_ports.clone();
+
+    // Ports that are not given are looked up by the name used last time and
+    // only asked for if they are not connected.
+    macro_rules! select_port {
+        ($selector:expr, $io:expr, $last:expr, $kind:literal) => {{
+            let last = $last.as_deref().and_then(|name| find_port(&$io, name));
+            let port = if let Some(selector) = $selector {
+                selector.find(&$io)?
+            } else if let Some(port) = last {
+                println!(
+                    concat!("Using ", $kind, " port {}"),
+                    $last.as_deref().unwrap_or_default()
+                );
+                port
+            } else {
+                let names = port_names(&$io);
+                if names.is_empty() {
+                    anyhow::bail!(concat!("No available MIDI ", $kind, " ports found"));
                 }
+
+                println!(concat!("Select ", $kind, " port:"));
+                for (i, name) in names.iter().enumerate() {
+                    println!("{}: {}", i, name);
+                }
+
+                read_line!()
+                    .parse::<PortSelector>()
+                    .map_err(anyhow::Error::msg)?
+                    .find(&$io)?
             };
+            $last = $io.port_name(&port).ok();
+            port
+        }};
+    }
+
+    let microphone = args.input_wav.is_none() && args.input == InputKind::Audio;
+    if microphone || args.input_wav.is_some() {
+        match args.mode {
+            Mode::Chord => anyhow::bail!("Audio input detects one note at a time, not chords"),
+            Mode::Dynamics => anyhow::bail!("Audio input cannot tell how hard a note is played"),
+            _ => {}
         }
+    }
+    if microphone && args.sustain_until_answered {
+        anyhow::bail!("Notes sustained until answered would be heard as the answer");
+    }
 
-        macro_rules! play_guess_note {
-            () => {
-                play_guess_note!(NOTE_ON, guess_note);
-                sleep_ms(args.guess_play_duration_ms);
-                play_guess_note!(NOTE_OFF, guess_note);
-            };
-            ($kind:expr, $note: expr) => {
-                conn_out
-                    .send(&[$kind, $note, VELOCITY])
-                    .map_err(|_| anyhow::anyhow!("cannot play note"))?;
-            };
+    let input: Box<dyn Input> = match (&args.input_wav, args.input) {
+        (Some(path), _) => Box::new(ScriptedInput::new(read_wav(path)?)),
+        (None, InputKind::Audio) => Box::new(MicInput::new()?),
+        (None, InputKind::Text) => Box::new(NoInput),
+        (None, InputKind::Midi) => {
+            let midi_in = MidiInput::new("guess-note-input")?;
+            let in_port = select_port!(&args.input_port, midi_in, last_ports.input, "input");
+            Box::new(MidirInput::connect(midi_in, &in_port, args.input_channel)?)
         }
+    };
 
-        play_guess_note!();
+    let sound = match &args.soundfont {
+        Some(path) => Sound::SoundFont(guess_note::soundfont::load(path)?),
+        None => Sound::Waveform(args.waveform),
+    };
 
-        let mut note = capture_next_note!();
-        if !args.non_interactive {
-            loop {
-                println!(
-                    "Last played note is {}. Confirm your guess? y/n",
-                    note_number_to_sign(note)
-                );
-                if read_line!().trim().to_lowercase() == "y" {
-                    break;
-                }
+    // Kept to silence the device on exit, the synthesizers stop by themselves.
+    let mut midi_output = None;
+    let output: Box<dyn Output> = match output_kind {
+        OutputKind::Midi => {
+            let midi_out = MidiOutput::new("guess-note-output")?;
+            let out_port = select_port!(&args.output_port, midi_out, last_ports.output, "output");
+            let output = Arc::new(Mutex::new(MidirOutput(
+                midi_out.connect(&out_port, "midir-test").unwrap(),
+            )));
+            midi_output = Some(output.clone());
+            Box::new(output)
+        }
+        OutputKind::Synth => match &args.wav {
+            Some(path) => Box::new(WavOutput::create(path, &sound)?),
+            None => Box::new(AudioOutput::new(&sound)?),
+        },
+    };
+    if let Some(path) = &ports_path {
+        if last_ports != saved_ports {
+            last_ports.save(path)?;
+        }
+    }
 
-                play_guess_note!();
+    let reference = args
+        .reference
+        .as_deref()
+        .map(|x| Reference::parse(x, &notation))
+        .transpose()
+        .map_err(anyhow::Error::msg)?;
 
-                note = capture_next_note!();
+    let mut session = Session::new(Settings {
+        mode: args.mode,
+        non_interactive: args.non_interactive,
+        typed_answers: args.input_wav.is_none() && args.input == InputKind::Text,
+        min_note: args.min_note,
+        max_note: args.max_note,
+        guess_play_duration_ms: args.guess_play_duration_ms,
+        max_interval: args.max_interval,
+        harmonic: args.harmonic,
+        answer_both: args.answer_both,
+        sevenths: args.sevenths,
+        chord_window_ms: args.chord_window_ms,
+        melody_length: args.melody_length,
+        key: args.key,
+        scale: args.scale,
+        cadence: args.cadence,
+        cadence_chord_ms: args.cadence_chord_ms,
+        rounds: args.rounds,
+        time_limit_s: args.time_limit_s,
+        notation,
+        scoring: args.scoring,
+        reference,
+        output_channel: args.output_channel,
+        instrument: args.instrument,
+        bank: args.bank,
+        velocity: args.velocity,
+        articulation: args.articulation,
+        sustain_until_answered: args.sustain_until_answered,
+    })?;
+    if args.adaptive {
+        let records = match &history {
+            Some(history) => history.load()?,
+            None => Vec::new(),
+        };
+        session = session.with_adaptive(Adaptive::new(&records, args.mode));
+    }
+
+    let mut game = Game::new(session, input, output, stdin.lock());
+    if let Some(history) = history {
+        game = game.with_history(history);
+    }
+
+    let summary = game.summary();
+    let output_channel = args.output_channel;
+    ctrlc::set_handler(move || {
+        // Notes may be left sounding, e.g. with --sustain-until-answered.
+        if let Some(output) = &mut midi_output {
+            for message in &Message::silence(output_channel) {
+                let _ = output.send(&message.to_bytes());
             }
         }
+        println!("\n\n{}", summary.lock().unwrap());
+        std::process::exit(0);
+    })?;
 
-        if note == guess_note {
-            println!(
-                "Correct, you played the right note ({})",
-                note_number_to_sign(note)
-            );
-        } else {
-            println!(
-                "Incorrect, you played {}, but the right one is {}",
-                note_number_to_sign(note),
-                note_number_to_sign(guess_note)
-            );
-        }
-    }
+    game.run()?;
+    println!("\n{}", game.summary().lock().unwrap());
+    Ok(())
 }
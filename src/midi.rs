@@ -0,0 +1,299 @@
+use std::collections::BTreeSet;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::backend::KeyEvent;
+
+const SUSTAIN_PEDAL: u8 = 64;
+const ALL_NOTES_OFF: u8 = 123;
+
+/// MIDI channel, numbered 1 to 16 in text and 0 to 15 in messages.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Channel(pub u8);
+
+impl FromStr for Channel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().parse::<u8>() {
+            Ok(x @ 1..=16) => Ok(Channel(x - 1)),
+            _ => Err(format!("invalid channel `{}`, expected 1 to 16", s)),
+        }
+    }
+}
+
+impl fmt::Display for Channel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0 + 1)
+    }
+}
+
+/// Channels to take input from.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChannelFilter {
+    Omni,
+    Only(Channel),
+}
+
+impl ChannelFilter {
+    pub fn matches(self, channel: Channel) -> bool {
+        match self {
+            ChannelFilter::Omni => true,
+            ChannelFilter::Only(x) => x == channel,
+        }
+    }
+}
+
+impl FromStr for ChannelFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "omni" => Ok(ChannelFilter::Omni),
+            _ => s
+                .parse()
+                .map(ChannelFilter::Only)
+                .map_err(|_| format!("invalid channel `{}`, expected 1 to 16 or omni", s)),
+        }
+    }
+}
+
+/// Channel voice message.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Message {
+    NoteOff {
+        channel: Channel,
+        note: u8,
+        velocity: u8,
+    },
+    NoteOn {
+        channel: Channel,
+        note: u8,
+        velocity: u8,
+    },
+    ControlChange {
+        channel: Channel,
+        controller: u8,
+        value: u8,
+    },
+    ProgramChange {
+        channel: Channel,
+        program: u8,
+    },
+}
+
+impl Message {
+    /// Parses a complete message, `None` for messages of other kinds.
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        let (&status, data) = bytes.split_first()?;
+        let channel = Channel(status & 0x0f);
+        Some(match (status & 0xf0, data) {
+            (0x80, &[note, velocity]) => Message::NoteOff {
+                channel,
+                note,
+                velocity,
+            },
+            (0x90, &[note, velocity]) => Message::NoteOn {
+                channel,
+                note,
+                velocity,
+            },
+            (0xb0, &[controller, value]) => Message::ControlChange {
+                channel,
+                controller,
+                value,
+            },
+            (0xc0, &[program]) => Message::ProgramChange { channel, program },
+            _ => return None,
+        })
+    }
+
+    /// Releases the sustain pedal and every note sounding on the channel.
+    pub fn silence(channel: Channel) -> [Message; 2] {
+        [
+            Message::ControlChange {
+                channel,
+                controller: SUSTAIN_PEDAL,
+                value: 0,
+            },
+            Message::ControlChange {
+                channel,
+                controller: ALL_NOTES_OFF,
+                value: 0,
+            },
+        ]
+    }
+
+    pub fn to_bytes(self) -> Vec<u8> {
+        match self {
+            Message::NoteOff {
+                channel,
+                note,
+                velocity,
+            } => vec![0x80 | channel.0, note, velocity],
+            Message::NoteOn {
+                channel,
+                note,
+                velocity,
+            } => vec![0x90 | channel.0, note, velocity],
+            Message::ControlChange {
+                channel,
+                controller,
+                value,
+            } => vec![0xb0 | channel.0, controller, value],
+            Message::ProgramChange { channel, program } => vec![0xc0 | channel.0, program],
+        }
+    }
+
+    pub fn channel(self) -> Channel {
+        match self {
+            Message::NoteOff { channel, .. }
+            | Message::NoteOn { channel, .. }
+            | Message::ControlChange { channel, .. }
+            | Message::ProgramChange { channel, .. } => channel,
+        }
+    }
+
+    /// Key press or release, a note on with zero velocity is a release.
+    pub fn key_event(self) -> Option<KeyEvent> {
+        match self {
+            Message::NoteOn { note, velocity, .. } if velocity != 0 => {
+                Some(KeyEvent::Press(note, velocity))
+            }
+            Message::NoteOn { note, .. } | Message::NoteOff { note, .. } => {
+                Some(KeyEvent::Release(note))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Turns the raw MIDI byte stream of a keyboard into key events, so that every
+/// key press and release is reported exactly once.
+///
+/// Messages may use running status and be split or joined arbitrarily between
+/// calls. A note on with zero velocity is a release, while repeated presses of
+/// a held key and releases of keys that are not held are dropped.
+pub struct KeyTracker {
+    channels: ChannelFilter,
+    running_status: Option<u8>,
+    data: Vec<u8>,
+    held: BTreeSet<u8>,
+}
+
+impl KeyTracker {
+    pub fn new(channels: ChannelFilter) -> Self {
+        KeyTracker {
+            channels,
+            running_status: None,
+            data: Vec::new(),
+            held: BTreeSet::new(),
+        }
+    }
+
+    /// Handles received bytes.
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<KeyEvent> {
+        let mut events = Vec::new();
+        for &byte in bytes {
+            match byte {
+                // Real-time messages may appear anywhere, even inside another
+                // message.
+                0xf8..=0xff => continue,
+                0x80..=0xef => {
+                    self.running_status = Some(byte);
+                    self.data.clear();
+                    continue;
+                }
+                0xf0..=0xf7 => {
+                    self.running_status = None;
+                    continue;
+                }
+                _ => {}
+            }
+
+            let status = match self.running_status {
+                Some(status) => status,
+                None => continue,
+            };
+            self.data.push(byte);
+            let length = match status & 0xf0 {
+                0xc0 | 0xd0 => 1,
+                _ => 2,
+            };
+            if self.data.len() < length {
+                continue;
+            }
+
+            let mut message = vec![status];
+            message.append(&mut self.data);
+            let event = Message::parse(&message)
+                .filter(|x| self.channels.matches(x.channel()))
+                .and_then(Message::key_event);
+            match event {
+                Some(KeyEvent::Press(note, velocity)) if self.held.insert(note) => {
+                    events.push(KeyEvent::Press(note, velocity));
+                }
+                Some(KeyEvent::Release(note)) if self.held.remove(&note) => {
+                    events.push(KeyEvent::Release(note));
+                }
+                _ => {}
+            }
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_on_any_channel() {
+        let message = Message::parse(&[0x93, 60, 0]).unwrap();
+        assert_eq!(message.channel(), Channel(3));
+        assert_eq!(message.key_event(), Some(KeyEvent::Release(60)));
+        assert_eq!(message.to_bytes(), vec![0x93, 60, 0]);
+
+        let message = Message::ProgramChange {
+            channel: Channel(15),
+            program: 40,
+        };
+        assert_eq!(Message::parse(&message.to_bytes()), Some(message));
+        assert_eq!(Message::parse(&[0xe0, 0, 64]), None);
+        assert_eq!(Message::parse(&[0x90, 60]), None);
+        assert_eq!(
+            Message::silence(Channel(2))[1].to_bytes(),
+            vec![0xb2, 123, 0]
+        );
+    }
+
+    #[test]
+    fn channels_are_numbered_from_one() {
+        assert_eq!("1".parse(), Ok(Channel(0)));
+        assert_eq!(Channel(15).to_string(), "16");
+        assert!("0".parse::<Channel>().is_err());
+        assert_eq!("omni".parse(), Ok(ChannelFilter::Omni));
+        assert!(!"2".parse::<ChannelFilter>().unwrap().matches(Channel(0)));
+    }
+
+    #[test]
+    fn tracker_reports_each_press_once() {
+        use KeyEvent::{Press, Release};
+
+        let mut tracker = KeyTracker::new(ChannelFilter::Omni);
+        // Running status with a zero velocity release, then a note off with a
+        // release velocity split between calls.
+        assert_eq!(
+            tracker.push(&[0x91, 60, 100, 64, 90, 60, 0]),
+            vec![Press(60, 100), Press(64, 90), Release(60)]
+        );
+        assert_eq!(tracker.held.iter().collect::<Vec<_>>(), vec![&64]);
+        assert_eq!(tracker.push(&[0x81, 64]), vec![]);
+        assert_eq!(tracker.push(&[0xf8, 64]), vec![Release(64)]);
+        // A repeated press of a held key and a release of a free one.
+        assert_eq!(
+            tracker.push(&[0x90, 62, 80, 62, 80, 0x80, 62, 64, 62, 64]),
+            vec![Press(62, 80), Release(62)]
+        );
+    }
+}
@@ -0,0 +1,572 @@
+use std::collections::BTreeSet;
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+pub const SIGN_COUNT: usize = 12;
+
+/// Semitones above C of the natural notes C to B.
+const NATURALS: [i16; 7] = [0, 2, 4, 5, 7, 9, 11];
+const LETTERS: [&str; 7] = ["C", "D", "E", "F", "G", "A", "B"];
+const FIXED_DO: [&str; 7] = ["Do", "Re", "Mi", "Fa", "Sol", "La", "Si"];
+/// Movable-do syllables of the semitones above the tonic.
+const MOVABLE_DO_SHARPS: [&str; SIGN_COUNT] = [
+    "do", "di", "re", "ri", "mi", "fa", "fi", "sol", "si", "la", "li", "ti",
+];
+const MOVABLE_DO_FLATS: [&str; SIGN_COUNT] = [
+    "do", "ra", "re", "me", "mi", "fa", "se", "sol", "le", "la", "te", "ti",
+];
+/// Letter and accidental of every pitch class.
+const SHARP_SPELLINGS: [(usize, i16); SIGN_COUNT] = [
+    (0, 0),
+    (0, 1),
+    (1, 0),
+    (1, 1),
+    (2, 0),
+    (3, 0),
+    (3, 1),
+    (4, 0),
+    (4, 1),
+    (5, 0),
+    (5, 1),
+    (6, 0),
+];
+const FLAT_SPELLINGS: [(usize, i16); SIGN_COUNT] = [
+    (0, 0),
+    (1, -1),
+    (1, 0),
+    (2, -1),
+    (2, 0),
+    (3, 0),
+    (4, -1),
+    (4, 0),
+    (5, -1),
+    (5, 0),
+    (6, -1),
+    (6, 0),
+];
+const MAJOR_DEGREES: [u8; 7] = [0, 2, 4, 5, 7, 9, 11];
+const MINOR_DEGREES: [u8; 7] = [0, 2, 3, 5, 7, 8, 10];
+
+pub fn pitch_classes(notes: &[u8]) -> BTreeSet<u8> {
+    notes.iter().map(|x| x % SIGN_COUNT as u8).collect()
+}
+
+/// Pitch class as semitones above C, from 0 to 11.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PitchClass(pub u8);
+
+impl fmt::Display for PitchClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&Notation::default().pitch_class(*self))
+    }
+}
+
+impl FromStr for PitchClass {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = Notation::default().parse(s)?;
+        match name.octave {
+            None => Ok(name.pitch_class()),
+            Some(_) => Err(format!("unexpected octave in `{}`", s)),
+        }
+    }
+}
+
+/// MIDI note number.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Note(pub u8);
+
+impl Note {
+    pub fn pitch_class(self) -> PitchClass {
+        PitchClass(self.0 % SIGN_COUNT as u8)
+    }
+}
+
+impl fmt::Display for Note {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&Notation::default().note(*self))
+    }
+}
+
+impl FromStr for Note {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Notation::default().parse_note(s)
+    }
+}
+
+/// Note name as typed by the user, the octave may be left out.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NoteName {
+    /// Semitones above the C of the written octave, e.g. -1 for `Cb`.
+    semitones: i16,
+    /// Octave in scientific pitch notation, where middle C is C4.
+    pub octave: Option<i16>,
+}
+
+impl NoteName {
+    pub fn pitch_class(&self) -> PitchClass {
+        PitchClass(self.semitones.rem_euclid(SIGN_COUNT as i16) as u8)
+    }
+
+    /// MIDI note number, the one with the same pitch class closest to `near`
+    /// if the octave is not given.
+    pub fn resolve(&self, near: u8) -> Option<u8> {
+        let note = match self.octave {
+            Some(octave) => (octave + 1) * SIGN_COUNT as i16 + self.semitones,
+            None => {
+                let near = i16::from(near);
+                let below = near - (near - self.semitones).rem_euclid(SIGN_COUNT as i16);
+                if near - below <= 6 {
+                    below
+                } else {
+                    below + SIGN_COUNT as i16
+                }
+            }
+        };
+        u8::try_from(note).ok().filter(|&x| x <= 127)
+    }
+}
+
+/// Language of note names.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Naming {
+    /// C D E F G A B with `#` and `b`.
+    English,
+    /// C D E F G A H with `-is` and `-es` suffixes, where B is B flat.
+    German,
+    /// Do Re Mi Fa Sol La Si with `#` and `b`.
+    FixedDo,
+    /// Solfège syllables relative to the tonic of the key.
+    MovableDo,
+}
+
+impl FromStr for Naming {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "english" => Ok(Naming::English),
+            "german" => Ok(Naming::German),
+            "fixed-do" => Ok(Naming::FixedDo),
+            "movable-do" => Ok(Naming::MovableDo),
+            _ => Err(format!(
+                "unknown naming `{}`, expected english, german, fixed-do or movable-do",
+                s
+            )),
+        }
+    }
+}
+
+/// How black keys are spelled.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Accidentals {
+    /// As in the key signature, e.g. E# in F# major and Bb in F major.
+    Key,
+    Sharps,
+    Flats,
+}
+
+impl FromStr for Accidentals {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "key" => Ok(Accidentals::Key),
+            "sharps" => Ok(Accidentals::Sharps),
+            "flats" => Ok(Accidentals::Flats),
+            _ => Err(format!(
+                "unknown accidentals `{}`, expected key, sharps or flats",
+                s
+            )),
+        }
+    }
+}
+
+/// Conventions for printing and parsing note names.
+#[derive(Clone, Copy, Debug)]
+pub struct Notation {
+    pub naming: Naming,
+    pub accidentals: Accidentals,
+    /// Tonic of the key, for spelling and movable do.
+    pub tonic: u8,
+    pub minor: bool,
+    /// Octave number of middle C (MIDI note 60), 4 in scientific pitch
+    /// notation and 3 in some other conventions.
+    pub middle_c_octave: i16,
+}
+
+impl Default for Notation {
+    fn default() -> Self {
+        Notation {
+            naming: Naming::English,
+            accidentals: Accidentals::Key,
+            tonic: 0,
+            minor: false,
+            middle_c_octave: 4,
+        }
+    }
+}
+
+fn accidental_signs(accidental: i16) -> String {
+    if accidental >= 0 {
+        "#".repeat(accidental as usize)
+    } else {
+        "b".repeat(-accidental as usize)
+    }
+}
+
+fn german_name(letter: usize, accidental: i16) -> String {
+    let name = ["C", "D", "E", "F", "G", "A", "H"][letter];
+    match (letter, accidental) {
+        (_, x) if x >= 0 => format!("{}{}", name, "is".repeat(x as usize)),
+        (6, -1) => "B".to_string(),
+        // Es and As rather than Ees and Aes.
+        (2, x) | (5, x) => format!("{}s{}", name, "es".repeat((-x - 1) as usize)),
+        (_, x) => format!("{}{}", name, "es".repeat(-x as usize)),
+    }
+}
+
+impl Notation {
+    fn scale_degrees(&self) -> &'static [u8; 7] {
+        if self.minor {
+            &MINOR_DEGREES
+        } else {
+            &MAJOR_DEGREES
+        }
+    }
+
+    fn uses_flats(&self) -> bool {
+        match self.accidentals {
+            Accidentals::Sharps => false,
+            Accidentals::Flats => true,
+            // F, Bb, Eb, Ab, Db and Gb major along with their relative minors.
+            Accidentals::Key if self.minor => {
+                [5, 10, 3, 8, 1, 6].contains(&((self.tonic + 3) % 12))
+            }
+            Accidentals::Key => [5, 10, 3, 8, 1].contains(&self.tonic),
+        }
+    }
+
+    /// Letter index and accidental of the pitch class.
+    fn spell(&self, pitch_class: PitchClass) -> (usize, i16) {
+        let spellings = if self.uses_flats() {
+            &FLAT_SPELLINGS
+        } else {
+            &SHARP_SPELLINGS
+        };
+        let PitchClass(pitch_class) = pitch_class;
+        if self.accidentals == Accidentals::Key {
+            let degree = (pitch_class + 12 - self.tonic % 12) % 12;
+            if let Some(i) = self.scale_degrees().iter().position(|&x| x == degree) {
+                let (tonic_letter, _) = spellings[usize::from(self.tonic % 12)];
+                let letter = (tonic_letter + i) % 7;
+                let accidental = (i16::from(pitch_class) - NATURALS[letter] + 6).rem_euclid(12) - 6;
+                return (letter, accidental);
+            }
+        }
+        spellings[usize::from(pitch_class)]
+    }
+
+    pub fn pitch_class(&self, pitch_class: PitchClass) -> String {
+        let (letter, accidental) = self.spell(pitch_class);
+        match self.naming {
+            Naming::English => format!("{}{}", LETTERS[letter], accidental_signs(accidental)),
+            Naming::German => german_name(letter, accidental),
+            Naming::FixedDo => format!("{}{}", FIXED_DO[letter], accidental_signs(accidental)),
+            Naming::MovableDo => {
+                let degree = usize::from((pitch_class.0 + 12 - self.tonic % 12) % 12);
+                if self.minor || self.uses_flats() {
+                    MOVABLE_DO_FLATS[degree].to_string()
+                } else {
+                    MOVABLE_DO_SHARPS[degree].to_string()
+                }
+            }
+        }
+    }
+
+    pub fn note(&self, note: Note) -> String {
+        format!(
+            "{}{}",
+            self.pitch_class(note.pitch_class()),
+            self.octave(note)
+        )
+    }
+
+    /// Octave number the note is written in.
+    pub fn octave(&self, note: Note) -> i16 {
+        let (letter, accidental) = self.spell(note.pitch_class());
+        // B#3 and Cb4 belong to the octave of their letter.
+        let written = match self.naming {
+            Naming::MovableDo => i16::from(note.pitch_class().0),
+            _ => NATURALS[letter] + accidental,
+        };
+        let octave = (i16::from(note.0) - written).div_euclid(12) - 1;
+        octave - 4 + self.middle_c_octave
+    }
+
+    /// Notes separated by dashes.
+    pub fn notes(&self, notes: &[u8]) -> String {
+        notes
+            .iter()
+            .map(|&x| self.note(Note(x)))
+            .collect::<Vec<_>>()
+            .join(" - ")
+    }
+
+    /// Pitch classes separated by spaces.
+    pub fn pitch_classes<'a>(&self, pitch_classes: impl IntoIterator<Item = &'a u8>) -> String {
+        pitch_classes
+            .into_iter()
+            .map(|&x| self.pitch_class(PitchClass(x)))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Note names accepted by `parse`, to show in prompts and errors.
+    pub(crate) fn examples(&self) -> &'static str {
+        match self.naming {
+            Naming::English => "C#4, Db or sol",
+            Naming::German => "Cis4, Des or H",
+            Naming::FixedDo => "Do#4, Reb or Sol",
+            Naming::MovableDo => "do4, ra or sol",
+        }
+    }
+
+    /// Parses a note name with an optional octave.
+    ///
+    /// English names also accept fixed-do syllables, and both accept double
+    /// accidentals as `##`, `x` or `bb`.
+    pub fn parse(&self, s: &str) -> Result<NoteName, String> {
+        let invalid = || format!("invalid note `{}`, expected e.g. {}", s, self.examples());
+        let lowercase = s.trim().to_lowercase();
+        let (semitones, rest) = match self.naming {
+            Naming::English | Naming::FixedDo => parse_letter(&lowercase),
+            Naming::German => parse_german(&lowercase),
+            Naming::MovableDo => parse_movable_do(&lowercase)
+                .map(|(degree, rest)| ((degree + i16::from(self.tonic)) % SIGN_COUNT as i16, rest)),
+        }
+        .ok_or_else(invalid)?;
+
+        let octave = match rest {
+            "" => None,
+            _ => {
+                let octave: i16 = rest.parse().map_err(|_| invalid())?;
+                Some(octave + 4 - self.middle_c_octave)
+            }
+        };
+        Ok(NoteName { semitones, octave })
+    }
+
+    /// Parses a note name with its octave.
+    pub fn parse_note(&self, s: &str) -> Result<Note, String> {
+        let name = self.parse(s)?;
+        match name.octave {
+            Some(_) => name
+                .resolve(0)
+                .map(Note)
+                .ok_or_else(|| format!("note `{}` is out of the MIDI range", s)),
+            None => Err(format!("missing octave in `{}`", s)),
+        }
+    }
+
+    /// Parses note names separated by spaces, commas or dashes.
+    pub fn parse_names(&self, s: &str) -> Result<Vec<NoteName>, String> {
+        s.split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|x| !x.is_empty() && *x != "-")
+            .map(|x| self.parse(x))
+            .collect()
+    }
+}
+
+/// Splits off a known prefix, trying longer ones first where they overlap.
+fn strip_any<'a, T: Copy>(s: &'a str, prefixes: &[(&str, T)]) -> Option<(T, &'a str)> {
+    prefixes
+        .iter()
+        .find(|(prefix, _)| s.starts_with(prefix))
+        .map(|&(prefix, x)| (x, &s[prefix.len()..]))
+}
+
+fn parse_letter(s: &str) -> Option<(i16, &str)> {
+    let (mut semitones, mut rest) = strip_any(
+        s,
+        &[
+            ("sol", 7),
+            ("do", 0),
+            ("re", 2),
+            ("mi", 4),
+            ("fa", 5),
+            ("so", 7),
+            ("la", 9),
+            ("si", 11),
+            ("ti", 11),
+            ("c", 0),
+            ("d", 2),
+            ("e", 4),
+            ("f", 5),
+            ("g", 7),
+            ("a", 9),
+            ("b", 11),
+        ],
+    )?;
+    while let Some((x, tail)) = strip_any(
+        rest,
+        &[
+            ("#", 1),
+            ("♯", 1),
+            ("x", 2),
+            ("𝄪", 2),
+            ("b", -1),
+            ("♭", -1),
+            ("𝄫", -2),
+        ],
+    ) {
+        semitones += x;
+        rest = tail;
+    }
+    Some((semitones, rest))
+}
+
+fn parse_german(s: &str) -> Option<(i16, &str)> {
+    let (mut semitones, mut rest) = strip_any(
+        s,
+        &[
+            // Es and As are flat on their own.
+            ("es", 3),
+            ("as", 8),
+            ("c", 0),
+            ("d", 2),
+            ("e", 4),
+            ("f", 5),
+            ("g", 7),
+            ("a", 9),
+            ("h", 11),
+            ("b", 10),
+        ],
+    )?;
+    while let Some((x, tail)) = strip_any(rest, &[("is", 1), ("es", -1), ("#", 1), ("♯", 1)]) {
+        semitones += x;
+        rest = tail;
+    }
+    Some((semitones, rest))
+}
+
+fn parse_movable_do(s: &str) -> Option<(i16, &str)> {
+    let syllables: Vec<(&str, i16)> = (0..)
+        .zip(MOVABLE_DO_SHARPS.iter().zip(&MOVABLE_DO_FLATS))
+        .flat_map(|(i, (&sharp, &flat))| vec![(sharp, i), (flat, i)])
+        .chain(std::iter::once(("so", 7)))
+        .collect();
+    // `sol` is the only syllable with a shorter one as its prefix.
+    strip_any(s, &syllables)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_are_parsed() {
+        let notation = Notation::default();
+        let note = |s: &str, near| notation.parse(s).unwrap().resolve(near);
+        assert_eq!(note("C#4", 0), Some(61));
+        assert_eq!(note("Cb4", 0), Some(59));
+        assert_eq!(note("c-1", 0), Some(0));
+        assert_eq!(note("Db", 61), Some(61));
+        assert_eq!(note("B", 61), Some(59));
+        assert_eq!(note("sol", 60), Some(55));
+        assert_eq!(note("Solb3", 0), Some(54));
+        assert_eq!(note("G9", 0), Some(127));
+        assert_eq!(note("G#9", 0), None);
+        assert!(notation.parse("H4").is_err());
+        assert!(notation.parse("C#y").is_err());
+    }
+
+    #[test]
+    fn names_invert_signs() {
+        for note in 0..=127 {
+            let sign = Note(note).to_string();
+            assert_eq!(sign.parse(), Ok(Note(note)));
+        }
+        assert_eq!(
+            Notation::default()
+                .parse_names("C4 - E4, G4")
+                .map(|x| x.len()),
+            Ok(3)
+        );
+    }
+
+    #[test]
+    fn spelling_follows_key() {
+        let notation = |naming, tonic, minor| Notation {
+            naming,
+            tonic,
+            minor,
+            ..Notation::default()
+        };
+
+        let f_sharp = notation(Naming::English, 6, false);
+        assert_eq!(f_sharp.pitch_classes(&[6, 5, 10]), "F# E# A#");
+        assert_eq!(f_sharp.note(Note(65)), "E#4");
+        let c_minor = notation(Naming::English, 0, true);
+        assert_eq!(c_minor.pitch_classes(&[3, 8, 10, 11]), "Eb Ab Bb B");
+        let german = notation(Naming::German, 3, false);
+        assert_eq!(german.pitch_classes(&[3, 8, 10, 11, 1]), "Es As B H Des");
+        let e_flat_minor = notation(Naming::English, 3, true);
+        assert_eq!(e_flat_minor.note(Note(59)), "Cb4");
+        let solfege = notation(Naming::FixedDo, 0, false);
+        assert_eq!(solfege.note(Note(67)), "Sol4");
+        let movable = notation(Naming::MovableDo, 7, false);
+        assert_eq!(movable.pitch_classes(&[7, 2, 6]), "do sol ti");
+    }
+
+    #[test]
+    fn naming_conventions_are_parsed() {
+        let german = Notation {
+            naming: Naming::German,
+            ..Notation::default()
+        };
+        let pitch_class = |notation: &Notation, s| notation.parse(s).unwrap().pitch_class().0;
+        assert_eq!(pitch_class(&german, "H"), 11);
+        assert_eq!(pitch_class(&german, "B"), 10);
+        assert_eq!(pitch_class(&german, "es"), 3);
+        assert_eq!(pitch_class(&german, "Fisis"), 7);
+        assert_eq!(pitch_class(&german, "Heses"), 9);
+        assert_eq!(pitch_class(&Notation::default(), "Ebb"), 2);
+        assert_eq!(pitch_class(&Notation::default(), "Fx"), 7);
+
+        let movable = Notation {
+            naming: Naming::MovableDo,
+            tonic: 2,
+            ..Notation::default()
+        };
+        assert_eq!(pitch_class(&movable, "mi"), 6);
+        assert_eq!(pitch_class(&movable, "te"), 0);
+
+        let yamaha = Notation {
+            middle_c_octave: 3,
+            ..Notation::default()
+        };
+        assert_eq!(yamaha.parse("C3").unwrap().resolve(0), Some(60));
+        assert_eq!(yamaha.note(Note(60)), "C3");
+
+        for &naming in &[
+            Naming::English,
+            Naming::German,
+            Naming::FixedDo,
+            Naming::MovableDo,
+        ] {
+            let notation = Notation {
+                naming,
+                ..Notation::default()
+            };
+            let examples = notation.examples().replace(" or ", ", ");
+            for example in examples.split(", ") {
+                assert!(notation.parse(example).is_ok(), "{}", example);
+            }
+        }
+    }
+}
@@ -0,0 +1,276 @@
+use std::path::Path;
+use std::sync::mpsc;
+use std::time::Duration;
+
+use anyhow::Context;
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+
+use crate::backend::{recv_event, Input, KeyEvent};
+
+/// Samples analyzed at once, enough for a few periods of the lowest note.
+const FRAME_SIZE: usize = 2048;
+/// Samples between the starts of consecutive frames.
+const HOP_SIZE: usize = 1024;
+/// Consecutive frames with the same pitch needed to press or release a note.
+const STABLE_FRAMES: usize = 3;
+const MIN_FREQUENCY: f32 = 60.0;
+const MAX_FREQUENCY: f32 = 2000.0;
+/// Level below which a frame is considered silent.
+const SILENCE_RMS: f32 = 0.01;
+/// Largest normalized difference accepted as a period by YIN.
+const YIN_THRESHOLD: f32 = 0.15;
+/// Velocity of detected notes, which have no dynamics of their own.
+const VELOCITY: u8 = 0x40;
+
+/// Estimates the fundamental frequency of a monophonic signal with YIN.
+///
+/// Returns `None` for silence and for sounds without a clear pitch.
+pub fn detect_pitch(samples: &[f32], sample_rate: u32) -> Option<f32> {
+    let rms = (samples.iter().map(|x| x * x).sum::<f32>() / samples.len() as f32).sqrt();
+    if rms < SILENCE_RMS {
+        return None;
+    }
+
+    let min_lag = ((sample_rate as f32 / MAX_FREQUENCY) as usize).max(2);
+    let max_lag = ((sample_rate as f32 / MIN_FREQUENCY) as usize).min(samples.len() / 2);
+    if min_lag + 1 >= max_lag {
+        return None;
+    }
+    let window = samples.len() - max_lag;
+
+    // Cumulative mean normalized difference of the signal and its shifted copy.
+    let mut difference = vec![1.0; max_lag + 1];
+    let mut sum = 0.0;
+    for lag in 1..=max_lag {
+        let d: f32 = (0..window)
+            .map(|i| (samples[i] - samples[i + lag]).powi(2))
+            .sum();
+        sum += d;
+        if sum > 0.0 {
+            difference[lag] = d * lag as f32 / sum;
+        }
+    }
+
+    let mut lag = (min_lag..max_lag).find(|&lag| difference[lag] < YIN_THRESHOLD)?;
+    while lag + 1 < max_lag && difference[lag + 1] < difference[lag] {
+        lag += 1;
+    }
+
+    // Refine the period between samples with a parabola through the minimum.
+    let (a, b, c) = (difference[lag - 1], difference[lag], difference[lag + 1]);
+    let curvature = a - 2.0 * b + c;
+    let shift = if curvature > 0.0 {
+        (a - c) / (2.0 * curvature)
+    } else {
+        0.0
+    };
+    Some(sample_rate as f32 / (lag as f32 + shift))
+}
+
+/// Nearest MIDI note number of the frequency.
+pub fn frequency_to_note(frequency: f32) -> Option<u8> {
+    let note = (69.0 + 12.0 * (frequency / 440.0).log2()).round();
+    if (0.0..=127.0).contains(&note) {
+        Some(note as u8)
+    } else {
+        None
+    }
+}
+
+/// Turns a stream of samples into key events of the detected notes.
+///
+/// A note is pressed once its pitch is detected in several consecutive
+/// frames and released when another note or silence is detected the same way.
+pub struct PitchTracker {
+    sample_rate: u32,
+    frame: Vec<f32>,
+    candidate: Option<u8>,
+    candidate_frames: usize,
+    note: Option<u8>,
+}
+
+impl PitchTracker {
+    pub fn new(sample_rate: u32) -> Self {
+        PitchTracker {
+            sample_rate,
+            frame: Vec::with_capacity(FRAME_SIZE),
+            candidate: None,
+            candidate_frames: 0,
+            note: None,
+        }
+    }
+
+    pub fn push(&mut self, samples: &[f32]) -> Vec<KeyEvent> {
+        let mut events = Vec::new();
+        for &x in samples {
+            self.frame.push(x);
+            if self.frame.len() < FRAME_SIZE {
+                continue;
+            }
+
+            let detected = detect_pitch(&self.frame, self.sample_rate).and_then(frequency_to_note);
+            self.frame.drain(..HOP_SIZE);
+
+            if detected == self.candidate {
+                self.candidate_frames += 1;
+            } else {
+                self.candidate = detected;
+                self.candidate_frames = 1;
+            }
+            if self.candidate_frames == STABLE_FRAMES && self.candidate != self.note {
+                events.extend(self.note.map(KeyEvent::Release));
+                events.extend(self.candidate.map(|x| KeyEvent::Press(x, VELOCITY)));
+                self.note = self.candidate;
+            }
+        }
+        events
+    }
+
+    /// Releases the note still sounding at the end of the stream.
+    pub fn finish(&mut self) -> Vec<KeyEvent> {
+        self.note
+            .take()
+            .map(KeyEvent::Release)
+            .into_iter()
+            .collect()
+    }
+}
+
+/// Notes sung or played into the default audio input device.
+pub struct MicInput {
+    _stream: cpal::Stream,
+    rx: mpsc::Receiver<KeyEvent>,
+}
+
+impl MicInput {
+    pub fn new() -> anyhow::Result<Self> {
+        let device = cpal::default_host()
+            .default_input_device()
+            .context("No audio input device found")?;
+        let config = device.default_input_config()?;
+        let channels = usize::from(config.channels());
+        let (tx, rx) = mpsc::channel();
+
+        macro_rules! build_stream {
+            ($sample:ty, $convert:expr) => {{
+                let tx = tx.clone();
+                let mut tracker = PitchTracker::new(config.sample_rate().0);
+                let mut mono = Vec::new();
+                device.build_input_stream(
+                    &config.config(),
+                    move |data: &[$sample], _| {
+                        mono.clear();
+                        mono.extend(data.chunks(channels).map(|frame| {
+                            frame.iter().map(|&x| $convert(x)).sum::<f32>() / channels as f32
+                        }));
+                        for event in tracker.push(&mono) {
+                            let _ = tx.send(event);
+                        }
+                    },
+                    |e| eprintln!("Audio input error: {}", e),
+                )?
+            }};
+        }
+
+        let stream = match config.sample_format() {
+            cpal::SampleFormat::F32 => build_stream!(f32, |x: f32| x),
+            cpal::SampleFormat::I16 => {
+                build_stream!(i16, |x: i16| f32::from(x) / f32::from(i16::MAX))
+            }
+            cpal::SampleFormat::U16 => {
+                build_stream!(u16, |x: u16| f32::from(x) / f32::from(i16::MAX) - 1.0)
+            }
+        };
+        stream.play()?;
+
+        Ok(MicInput {
+            _stream: stream,
+            rx,
+        })
+    }
+}
+
+impl Input for MicInput {
+    fn recv(&mut self, timeout: Option<Duration>) -> anyhow::Result<Option<KeyEvent>> {
+        recv_event(&self.rx, timeout)
+    }
+
+    fn pending(&mut self) -> Vec<KeyEvent> {
+        self.rx.try_iter().collect()
+    }
+
+    fn hears_output(&self) -> bool {
+        true
+    }
+}
+
+/// Detects the notes of a recording, e.g. to replay it with `ScriptedInput`.
+pub fn read_wav(path: impl AsRef<Path>) -> anyhow::Result<Vec<KeyEvent>> {
+    let path = path.as_ref();
+    let mut reader =
+        hound::WavReader::open(path).with_context(|| format!("cannot open {}", path.display()))?;
+    let spec = reader.spec();
+    let samples: Vec<f32> = match spec.sample_format {
+        hound::SampleFormat::Float => reader.samples::<f32>().collect::<Result<_, _>>()?,
+        hound::SampleFormat::Int => {
+            let scale = (1u32 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .samples::<i32>()
+                .map(|x| x.map(|x| x as f32 / scale))
+                .collect::<Result<_, _>>()?
+        }
+    };
+
+    let channels = usize::from(spec.channels);
+    let mono: Vec<f32> = samples
+        .chunks(channels)
+        .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+        .collect();
+
+    let mut tracker = PitchTracker::new(spec.sample_rate);
+    let mut events = tracker.push(&mono);
+    events.extend(tracker.finish());
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::synth::{note_frequency, SAMPLE_RATE};
+
+    fn tone(frequency: f32, seconds: f32) -> Vec<f32> {
+        (0..(seconds * SAMPLE_RATE as f32) as usize)
+            .map(|i| {
+                let t = i as f32 / SAMPLE_RATE as f32;
+                0.5 * (2.0 * std::f32::consts::PI * frequency * t).sin()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn pitch_of_a_tone() {
+        for &note in &[40, 57, 69, 84] {
+            let frequency = detect_pitch(&tone(note_frequency(note), 0.05), SAMPLE_RATE).unwrap();
+            assert_eq!(frequency_to_note(frequency), Some(note));
+        }
+        assert_eq!(detect_pitch(&[0.0; FRAME_SIZE], SAMPLE_RATE), None);
+    }
+
+    #[test]
+    fn tracker_presses_and_releases_notes() {
+        let mut tracker = PitchTracker::new(SAMPLE_RATE);
+        let mut events = tracker.push(&tone(note_frequency(60), 0.5));
+        events.extend(tracker.push(&tone(note_frequency(64), 0.5)));
+        events.extend(tracker.push(&vec![0.0; SAMPLE_RATE as usize / 2]));
+        events.extend(tracker.finish());
+        assert_eq!(
+            events,
+            vec![
+                KeyEvent::Press(60, VELOCITY),
+                KeyEvent::Release(60),
+                KeyEvent::Press(64, VELOCITY),
+                KeyEvent::Release(64),
+            ]
+        );
+    }
+}
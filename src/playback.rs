@@ -0,0 +1,191 @@
+use std::fmt;
+use std::str::FromStr;
+
+use rand::Rng;
+
+/// Largest change of a humanized velocity.
+const HUMANIZE_SPREAD: u8 = 12;
+
+/// Dynamic markings from the softest along with the velocities they are
+/// played with.
+const DYNAMICS: [(&str, u8); 6] = [
+    ("pp", 33),
+    ("p", 49),
+    ("mp", 64),
+    ("mf", 80),
+    ("f", 96),
+    ("ff", 112),
+];
+
+/// How hard notes are played.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Velocity {
+    Fixed(u8),
+    /// Uniformly random from the inclusive range.
+    Random(u8, u8),
+    /// Slightly varying around the value, like a human player.
+    Humanized(u8),
+}
+
+impl Default for Velocity {
+    fn default() -> Self {
+        Velocity::Fixed(0x40)
+    }
+}
+
+impl Velocity {
+    pub fn sample(self, rng: &mut impl Rng) -> u8 {
+        match self {
+            Velocity::Fixed(x) => x,
+            Velocity::Random(min, max) => rng.gen_range(min..=max),
+            Velocity::Humanized(x) => {
+                let min = x.saturating_sub(HUMANIZE_SPREAD).max(1);
+                let max = x.saturating_add(HUMANIZE_SPREAD).min(127);
+                rng.gen_range(min..=max)
+            }
+        }
+    }
+}
+
+impl FromStr for Velocity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || {
+            format!(
+                "invalid velocity `{}`, expected a value from 1 to 127 like 80, \
+                 a range like 40-100 or a humanized value like ~80",
+                s
+            )
+        };
+        let value = |x: &str| match x.trim().parse() {
+            Ok(x @ 1..=127) => Ok(x),
+            _ => Err(error()),
+        };
+
+        if let Some(x) = s.strip_prefix('~') {
+            return Ok(Velocity::Humanized(value(x)?));
+        }
+        match s.split_once('-') {
+            Some((min, max)) => {
+                let (min, max) = (value(min)?, value(max)?);
+                if min > max {
+                    return Err(error());
+                }
+                Ok(Velocity::Random(min, max))
+            }
+            None => Ok(Velocity::Fixed(value(s)?)),
+        }
+    }
+}
+
+/// How consecutive notes are connected.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Articulation {
+    /// Every note sounds for the whole play duration.
+    Normal,
+    /// Every note is released after half of the play duration.
+    Staccato,
+    /// Every note is released only once the next one starts.
+    Legato,
+    /// Notes are held by the sustain pedal until the end of the phrase.
+    Pedal,
+}
+
+impl FromStr for Articulation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "normal" => Ok(Articulation::Normal),
+            "staccato" => Ok(Articulation::Staccato),
+            "legato" => Ok(Articulation::Legato),
+            "pedal" => Ok(Articulation::Pedal),
+            _ => Err(format!(
+                "unknown articulation `{}`, expected normal, staccato, legato or pedal",
+                s
+            )),
+        }
+    }
+}
+
+/// Dynamic level, as an index into the levels from pp to ff.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Dynamic(usize);
+
+impl Dynamic {
+    pub const MEZZO_FORTE: Dynamic = Dynamic(3);
+
+    pub fn all() -> Vec<Dynamic> {
+        (0..DYNAMICS.len()).map(Dynamic).collect()
+    }
+
+    pub fn velocity(self) -> u8 {
+        DYNAMICS[self.0].1
+    }
+
+    /// Level with the nearest velocity.
+    pub fn from_velocity(velocity: u8) -> Self {
+        Self::all()
+            .into_iter()
+            .min_by_key(|x| x.velocity().abs_diff(velocity))
+            .unwrap()
+    }
+
+    /// Number of levels from `other` up to this one, negative if softer.
+    pub fn steps_from(self, other: Dynamic) -> i32 {
+        self.0 as i32 - other.0 as i32
+    }
+}
+
+impl fmt::Display for Dynamic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(DYNAMICS[self.0].0)
+    }
+}
+
+impl FromStr for Dynamic {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_lowercase();
+        match DYNAMICS.iter().position(|(x, _)| *x == s) {
+            Some(i) => Ok(Dynamic(i)),
+            None => Err(format!(
+                "unknown dynamic `{}`, expected pp, p, mp, mf, f or ff",
+                s
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn velocities() {
+        assert_eq!("80".parse(), Ok(Velocity::Fixed(80)));
+        assert_eq!("40-100".parse(), Ok(Velocity::Random(40, 100)));
+        assert_eq!("~120".parse(), Ok(Velocity::Humanized(120)));
+        assert!("0".parse::<Velocity>().is_err());
+        assert!("100-40".parse::<Velocity>().is_err());
+
+        let mut rng = rand::thread_rng();
+        for _ in 0..100 {
+            assert!((40..=100).contains(&Velocity::Random(40, 100).sample(&mut rng)));
+            assert!((108..=127).contains(&Velocity::Humanized(120).sample(&mut rng)));
+        }
+    }
+
+    #[test]
+    fn dynamics_from_velocity() {
+        let mf: Dynamic = "mf".parse().unwrap();
+        assert_eq!(mf.to_string(), "mf");
+        assert_eq!(Dynamic::from_velocity(mf.velocity()), mf);
+        assert_eq!(Dynamic::from_velocity(1).to_string(), "pp");
+        assert_eq!(Dynamic::from_velocity(127).to_string(), "ff");
+        assert_eq!("FF".parse::<Dynamic>().unwrap().steps_from(mf), 2);
+        assert!("fff".parse::<Dynamic>().is_err());
+    }
+}
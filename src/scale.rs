@@ -0,0 +1,116 @@
+use std::str::FromStr;
+
+use crate::note::PitchClass;
+
+const SCALES: [(&str, &[u8]); 14] = [
+    ("chromatic", &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11]),
+    ("major", &[0, 2, 4, 5, 7, 9, 11]),
+    ("minor", &[0, 2, 3, 5, 7, 8, 10]),
+    ("harmonic-minor", &[0, 2, 3, 5, 7, 8, 11]),
+    ("melodic-minor", &[0, 2, 3, 5, 7, 9, 11]),
+    ("ionian", &[0, 2, 4, 5, 7, 9, 11]),
+    ("dorian", &[0, 2, 3, 5, 7, 9, 10]),
+    ("phrygian", &[0, 1, 3, 5, 7, 8, 10]),
+    ("lydian", &[0, 2, 4, 6, 7, 9, 11]),
+    ("mixolydian", &[0, 2, 4, 5, 7, 9, 10]),
+    ("aeolian", &[0, 2, 3, 5, 7, 8, 10]),
+    ("locrian", &[0, 1, 3, 5, 6, 8, 10]),
+    ("pentatonic", &[0, 2, 4, 7, 9]),
+    ("minor-pentatonic", &[0, 3, 5, 7, 10]),
+];
+
+/// Set of pitch classes counted in semitones from the key tonic.
+#[derive(Clone, PartialEq)]
+pub struct Scale {
+    pitch_classes: Vec<u8>,
+}
+
+impl FromStr for Scale {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.to_lowercase();
+        let name = match s.as_str() {
+            "natural-minor" => "minor",
+            "major-pentatonic" => "pentatonic",
+            x => x,
+        };
+        if let Some((_, pitch_classes)) = SCALES.iter().find(|(x, _)| *x == name) {
+            return Ok(Scale {
+                pitch_classes: pitch_classes.to_vec(),
+            });
+        }
+
+        let mut pitch_classes = s
+            .split(',')
+            .map(|x| match x.trim().parse::<u8>() {
+                Ok(x) if x < 12 => Ok(x),
+                _ => Err(format!(
+                    "unknown scale `{}`, expected one of {} or comma-separated semitones 0-11",
+                    s,
+                    SCALES
+                        .iter()
+                        .map(|(x, _)| *x)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        pitch_classes.sort_unstable();
+        pitch_classes.dedup();
+        Ok(Scale { pitch_classes })
+    }
+}
+
+impl Default for Scale {
+    fn default() -> Self {
+        Scale {
+            pitch_classes: SCALES[0].1.to_vec(),
+        }
+    }
+}
+
+impl Scale {
+    pub fn contains(&self, tonic: u8, note: u8) -> bool {
+        self.pitch_classes
+            .contains(&((note % 12 + 12 - tonic) % 12))
+    }
+
+    /// Whether the scale sounds minor, i.e. has a minor but not a major third.
+    pub fn is_minor(&self) -> bool {
+        self.pitch_classes.contains(&3) && !self.pitch_classes.contains(&4)
+    }
+
+    /// I-IV-V-I (or i-iv-V-i for minor scales) cadence around the third octave.
+    pub fn cadence(&self, tonic: u8) -> [Vec<u8>; 4] {
+        let tonic = 48 + tonic;
+        let (i, iv): (&[u8], &[u8]) = if self.is_minor() {
+            (&[0, 3, 7], &[0, 5, 8])
+        } else {
+            (&[0, 4, 7], &[0, 5, 9])
+        };
+        let chord = |intervals: &[u8]| intervals.iter().map(|x| tonic + x).collect::<Vec<_>>();
+
+        [
+            chord(i),
+            chord(iv),
+            vec![tonic - 1, tonic + 2, tonic + 7],
+            chord(i),
+        ]
+    }
+}
+
+/// Key tonic as a pitch class, e.g. `C`, `F#` or `Bb`.
+#[derive(Clone, Copy)]
+pub struct Key(pub u8);
+
+impl FromStr for Key {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.parse() {
+            Ok(PitchClass(x)) => Ok(Key(x)),
+            Err(_) => Err(format!("invalid key `{}`, expected e.g. C, F# or Bb", s)),
+        }
+    }
+}
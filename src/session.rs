@@ -0,0 +1,872 @@
+use std::fmt;
+use std::str::FromStr;
+
+use rand::seq::SliceRandom;
+
+use crate::adaptive::Adaptive;
+use crate::chord::{ChordKind, SEVENTHS, TRIADS};
+use crate::instrument::Instrument;
+use crate::interval::interval_name;
+use crate::midi::Channel;
+use crate::note::{pitch_classes, Notation, Note, PitchClass, SIGN_COUNT};
+use crate::playback::{Articulation, Dynamic, Velocity};
+use crate::scale::{Key, Scale};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
+    Note,
+    Interval,
+    Chord,
+    Melody,
+    /// Dynamic level of a note played with a random velocity.
+    Dynamics,
+}
+
+impl FromStr for Mode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "note" => Ok(Mode::Note),
+            "interval" => Ok(Mode::Interval),
+            "chord" => Ok(Mode::Chord),
+            "melody" => Ok(Mode::Melody),
+            "dynamics" => Ok(Mode::Dynamics),
+            _ => Err(format!(
+                "unknown mode `{}`, expected note, interval, chord, melody or dynamics",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Mode::Note => "note",
+            Mode::Interval => "interval",
+            Mode::Chord => "chord",
+            Mode::Melody => "melody",
+            Mode::Dynamics => "dynamics",
+        })
+    }
+}
+
+/// How answers in the wrong octave are scored.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Scoring {
+    /// Only the exact note is right.
+    Exact,
+    /// The right pitch class in the wrong octave earns half the credit.
+    Partial,
+    /// The octave is ignored.
+    PitchClass,
+}
+
+impl FromStr for Scoring {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "exact" => Ok(Scoring::Exact),
+            "partial" => Ok(Scoring::Partial),
+            "pitch-class" => Ok(Scoring::PitchClass),
+            _ => Err(format!(
+                "unknown scoring `{}`, expected exact, partial or pitch-class",
+                s
+            )),
+        }
+    }
+}
+
+impl Scoring {
+    /// Credit for playing `answer` instead of `target`.
+    fn note_score(self, answer: u8, target: u8) -> f32 {
+        if answer == target {
+            1.0
+        } else if octave_offset(answer, target).is_none() {
+            0.0
+        } else {
+            match self {
+                Scoring::Exact => 0.0,
+                Scoring::Partial => 0.5,
+                Scoring::PitchClass => 1.0,
+            }
+        }
+    }
+}
+
+/// Note played before every round to guess relative to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Reference {
+    Note(u8),
+    /// Tonic of the key closest to the middle of the note range.
+    Tonic,
+    /// Random note of the scale in the note range.
+    Random,
+}
+
+impl Reference {
+    /// Parses a note name written in the notation, `tonic` or `random`.
+    pub fn parse(s: &str, notation: &Notation) -> Result<Self, String> {
+        match s {
+            "tonic" => Ok(Reference::Tonic),
+            "random" => Ok(Reference::Random),
+            _ => match notation.parse_note(s) {
+                Ok(Note(x)) => Ok(Reference::Note(x)),
+                Err(_) => Err(format!(
+                    "invalid reference `{}`, expected a note like {}, tonic or random",
+                    s,
+                    notation.note(Note(69))
+                )),
+            },
+        }
+    }
+}
+
+/// Share of the score taken away by every hint used.
+const HINT_PENALTY: f32 = 0.25;
+
+/// Octaves between two different notes of the same pitch class.
+fn octave_offset(answer: u8, target: u8) -> Option<i16> {
+    let semitones = i16::from(answer) - i16::from(target);
+    if semitones != 0 && semitones % SIGN_COUNT as i16 == 0 {
+        Some(semitones / SIGN_COUNT as i16)
+    } else {
+        None
+    }
+}
+
+/// Number of leading target notes without an answer, the reference of an
+/// interval answered with its top note alone.
+pub(crate) fn unanswered_notes(mode: Mode, answer: &[u8]) -> usize {
+    if mode == Mode::Interval && answer.len() == 1 {
+        1
+    } else {
+        0
+    }
+}
+
+/// How far and in which direction a played note misses the target.
+fn distance(answer: u8, target: u8) -> String {
+    let semitones = answer.abs_diff(target);
+    format!(
+        "{} semitone{} too {}",
+        semitones,
+        if semitones == 1 { "" } else { "s" },
+        if answer > target { "high" } else { "low" }
+    )
+}
+
+pub struct Settings {
+    pub mode: Mode,
+    pub non_interactive: bool,
+    /// Answers are typed note names instead of played notes.
+    pub typed_answers: bool,
+    pub min_note: u8,
+    pub max_note: u8,
+    pub guess_play_duration_ms: u64,
+    pub max_interval: u8,
+    pub harmonic: bool,
+    pub answer_both: bool,
+    pub sevenths: bool,
+    pub chord_window_ms: u64,
+    pub melody_length: usize,
+    pub key: Key,
+    pub scale: Scale,
+    pub cadence: bool,
+    pub cadence_chord_ms: u64,
+    pub rounds: Option<u32>,
+    pub time_limit_s: Option<u64>,
+    pub notation: Notation,
+    pub scoring: Scoring,
+    pub reference: Option<Reference>,
+    /// Channel to play notes on.
+    pub output_channel: Channel,
+    pub instrument: Option<Instrument>,
+    /// Bank to select along with the instrument, from 0 to 16383.
+    pub bank: Option<u16>,
+    pub velocity: Velocity,
+    pub articulation: Articulation,
+    /// Target notes ring until the answer is played.
+    pub sustain_until_answered: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            mode: Mode::Note,
+            non_interactive: false,
+            typed_answers: false,
+            min_note: 36,
+            max_note: 96,
+            guess_play_duration_ms: 150,
+            max_interval: 12,
+            harmonic: false,
+            answer_both: false,
+            sevenths: false,
+            chord_window_ms: 2000,
+            melody_length: 4,
+            key: Key(0),
+            scale: Scale::default(),
+            cadence: false,
+            cadence_chord_ms: 600,
+            rounds: None,
+            time_limit_s: None,
+            notation: Notation::default(),
+            scoring: Scoring::Exact,
+            reference: None,
+            output_channel: Channel::default(),
+            instrument: None,
+            bank: None,
+            velocity: Velocity::default(),
+            articulation: Articulation::Normal,
+            sustain_until_answered: false,
+        }
+    }
+}
+
+/// Training session generating rounds according to its settings.
+pub struct Session {
+    settings: Settings,
+    scale_notes: Vec<u8>,
+    intervals: Vec<(u8, u8)>,
+    chords: Vec<(u8, &'static ChordKind)>,
+    adaptive: Option<Adaptive>,
+}
+
+fn random_melody(
+    scale_notes: &[u8],
+    length: usize,
+    max_leap: u8,
+    weight: impl Fn(u8) -> f64,
+) -> Vec<u8> {
+    let mut rng = rand::thread_rng();
+    let mut melody: Vec<u8> = Vec::with_capacity(length);
+    while melody.len() < length {
+        let candidates: Vec<u8> = scale_notes
+            .iter()
+            .copied()
+            .filter(|&x| match melody.last() {
+                Some(&prev) => x.abs_diff(prev) <= max_leap,
+                None => true,
+            })
+            .collect();
+        melody.push(
+            *candidates
+                .choose_weighted(&mut rng, |&x| weight(x))
+                .expect("scale is checked at startup"),
+        );
+    }
+    melody
+}
+
+/// Items matching `f`, or all of them if none does.
+fn restrict<T: Copy>(items: &[T], f: impl Fn(&T) -> bool) -> Vec<T> {
+    let restricted: Vec<T> = items.iter().copied().filter(f).collect();
+    if restricted.is_empty() {
+        items.to_vec()
+    } else {
+        restricted
+    }
+}
+
+impl Session {
+    pub fn new(settings: Settings) -> anyhow::Result<Self> {
+        if settings.min_note > settings.max_note {
+            anyhow::bail!("Note range cannot be empty");
+        }
+        if settings.bank.is_some_and(|x| x > 0x3fff) {
+            anyhow::bail!("Bank must be at most 16383");
+        }
+
+        let in_scale = |x: &u8| settings.scale.contains(settings.key.0, *x);
+        let scale_notes: Vec<u8> = (settings.min_note..=settings.max_note)
+            .filter(in_scale)
+            .collect();
+        if scale_notes.is_empty() {
+            anyhow::bail!("Note range contains no notes of the scale");
+        }
+
+        let intervals: Vec<(u8, u8)> = scale_notes
+            .iter()
+            .flat_map(|&x| scale_notes.iter().map(move |&y| (x, y)))
+            .filter(|&(x, y)| x < y && y - x <= settings.max_interval)
+            .collect();
+        if settings.mode == Mode::Interval && intervals.is_empty() {
+            anyhow::bail!("Note range is too narrow for intervals");
+        }
+
+        let chords: Vec<(u8, &'static ChordKind)> = TRIADS
+            .iter()
+            .chain(if settings.sevenths {
+                &SEVENTHS[..]
+            } else {
+                &[]
+            })
+            .flat_map(|kind| scale_notes.iter().map(move |&root| (root, kind)))
+            .filter(|&(root, kind)| {
+                kind.span() <= settings.max_note - root && kind.notes(root).iter().all(in_scale)
+            })
+            .collect();
+        if settings.mode == Mode::Chord && chords.is_empty() {
+            anyhow::bail!("Note range is too narrow for chords");
+        }
+
+        if settings.mode == Mode::Melody && settings.melody_length == 0 {
+            anyhow::bail!("Melody cannot be empty");
+        }
+
+        Ok(Session {
+            settings,
+            scale_notes,
+            intervals,
+            chords,
+            adaptive: None,
+        })
+    }
+
+    /// Generates weak notes and intervals more often and widens the note
+    /// range as the accuracy improves.
+    pub fn with_adaptive(mut self, adaptive: Adaptive) -> Self {
+        self.adaptive = Some(adaptive);
+        self
+    }
+
+    /// Takes a finished round into account for the next ones.
+    pub fn record(&mut self, result: &RoundResult) {
+        if let Some(adaptive) = &mut self.adaptive {
+            adaptive.record(result);
+        }
+    }
+
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    /// Generates a new target to guess.
+    pub fn next_round(&self) -> Round {
+        let mut rng = rand::thread_rng();
+        let mode = self.settings.mode;
+        let mut chord = None;
+        let mut dynamic = None;
+
+        let adaptive = self.adaptive.as_ref();
+        let (min_note, max_note) = adaptive
+            .map_or((self.settings.min_note, self.settings.max_note), |x| {
+                x.range(self.settings.min_note, self.settings.max_note)
+            });
+        let in_range = |x: u8| (min_note..=max_note).contains(&x);
+        let note_weight = |x: u8| adaptive.map_or(1.0, |a| a.note_weight(x));
+        let interval_weight = |x: u8| adaptive.map_or(1.0, |a| a.interval_weight(x));
+        let scale_notes = restrict(&self.scale_notes, |&x| in_range(x));
+
+        let target = match mode {
+            Mode::Note => vec![*scale_notes
+                .choose_weighted(&mut rng, |&x| note_weight(x))
+                .unwrap()],
+            Mode::Interval => {
+                let intervals = restrict(&self.intervals, |&(x, y)| in_range(x) && in_range(y));
+                let (reference, top) = *intervals
+                    .choose_weighted(&mut rng, |&(x, y)| interval_weight(y - x) * note_weight(y))
+                    .unwrap();
+                vec![reference, top]
+            }
+            Mode::Chord => {
+                let chords = restrict(&self.chords, |&(root, kind)| {
+                    in_range(root) && in_range(root + kind.span())
+                });
+                let (root, kind) = *chords
+                    .choose_weighted(&mut rng, |&(root, kind)| {
+                        let notes = kind.notes(root);
+                        notes.iter().map(|&x| note_weight(x)).sum::<f64>() / notes.len() as f64
+                    })
+                    .unwrap();
+                chord = Some(kind);
+                kind.notes(root)
+            }
+            Mode::Melody => random_melody(
+                &scale_notes,
+                self.settings.melody_length,
+                self.settings.max_interval,
+                note_weight,
+            ),
+            // The same note every round, so that only the loudness changes.
+            Mode::Dynamics => {
+                dynamic = Dynamic::all().choose(&mut rng).copied();
+                vec![scale_notes[scale_notes.len() / 2]]
+            }
+        };
+
+        let reference = self.settings.reference.map(|x| match x {
+            Reference::Note(note) => note,
+            Reference::Tonic => {
+                let center = min_note + (max_note - min_note) / 2;
+                (0..=127)
+                    .filter(|x| x % 12 == self.settings.key.0)
+                    .min_by_key(|x: &u8| x.abs_diff(center))
+                    .unwrap()
+            }
+            Reference::Random => *scale_notes.choose(&mut rng).unwrap(),
+        });
+        Round {
+            mode,
+            target,
+            chord,
+            dynamic,
+            reference,
+            notation: self.settings.notation,
+            scoring: self.settings.scoring,
+        }
+    }
+}
+
+/// Single guess with its target notes.
+pub struct Round {
+    mode: Mode,
+    target: Vec<u8>,
+    chord: Option<&'static ChordKind>,
+    dynamic: Option<Dynamic>,
+    reference: Option<u8>,
+    notation: Notation,
+    scoring: Scoring,
+}
+
+impl Round {
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// Notes to play, lowest first for intervals and chords.
+    pub fn target(&self) -> &[u8] {
+        &self.target
+    }
+
+    /// Level to play the target with in dynamics mode.
+    pub fn dynamic(&self) -> Option<Dynamic> {
+        self.dynamic
+    }
+
+    /// Note to play and name before the target.
+    pub fn reference(&self) -> Option<u8> {
+        self.reference
+    }
+
+    /// Whether the target notes should sound together.
+    pub fn is_simultaneous(&self, harmonic: bool) -> bool {
+        match self.mode {
+            Mode::Interval => harmonic,
+            Mode::Chord => true,
+            _ => false,
+        }
+    }
+
+    /// Scores played notes against the target.
+    ///
+    /// An interval is answered either with its top note or with both notes,
+    /// in any order.
+    /// Chords are compared by pitch class, in any order and octave, other
+    /// notes in the wrong octave earn credit according to the scoring.
+    ///
+    /// In dynamics mode the answer is the velocity of the played key, which
+    /// is right if it is nearest to the velocity of the target level.
+    pub fn submit(&self, answer: &[u8]) -> RoundResult {
+        let note_scores = |answer: &[u8]| -> Vec<f32> {
+            answer
+                .iter()
+                .zip(&self.target[unanswered_notes(self.mode, answer)..])
+                .map(|(&x, &y)| self.scoring.note_score(x, y))
+                .collect()
+        };
+        let mut dynamics = None;
+        let (answer, score) = match self.mode {
+            Mode::Note => (
+                answer.to_vec(),
+                note_scores(answer).into_iter().fold(1.0, f32::min),
+            ),
+            Mode::Interval => {
+                // Both notes may be played or typed in any order.
+                let answer = match *answer {
+                    [x, y] => vec![x.min(y), x.max(y)],
+                    _ => answer.to_vec(),
+                };
+                let score = note_scores(&answer).into_iter().fold(1.0, f32::min);
+                (answer, score)
+            }
+            Mode::Chord => {
+                let score = if pitch_classes(answer) == pitch_classes(&self.target) {
+                    1.0
+                } else {
+                    0.0
+                };
+                (answer.to_vec(), score)
+            }
+            Mode::Melody => {
+                let score = note_scores(answer).iter().sum::<f32>() / self.target.len() as f32;
+                (answer.to_vec(), score)
+            }
+            // No notes are answered, only the level of the played velocity.
+            Mode::Dynamics => {
+                let target = self
+                    .dynamic
+                    .expect("dynamics rounds are generated with a level");
+                let played = Dynamic::from_velocity(answer[0]);
+                dynamics = Some((target, played));
+                (Vec::new(), if played == target { 1.0 } else { 0.0 })
+            }
+        };
+
+        RoundResult {
+            mode: self.mode,
+            target: self.target.clone(),
+            answer,
+            score,
+            chord: self.chord,
+            dynamics,
+            notation: self.notation,
+            hints: 0,
+        }
+    }
+}
+
+/// Outcome of a round, displayed as a human-readable feedback.
+pub struct RoundResult {
+    pub mode: Mode,
+    pub target: Vec<u8>,
+    /// Played notes, only the top one for an interval answered with it alone
+    /// and none in dynamics mode.
+    pub answer: Vec<u8>,
+    /// Share of the answer that is right, from 0 to 1.
+    pub score: f32,
+    chord: Option<&'static ChordKind>,
+    /// Target and played level in dynamics mode.
+    dynamics: Option<(Dynamic, Dynamic)>,
+    notation: Notation,
+    /// Number of hints used before answering.
+    pub hints: u32,
+}
+
+impl RoundResult {
+    /// Whether the answer is fully right without any hints.
+    pub fn is_correct(&self) -> bool {
+        self.score >= 1.0
+    }
+
+    /// Takes a share of the score away for every hint used.
+    pub fn with_hints(mut self, hints: u32) -> Self {
+        self.score *= self.hint_factor(hints);
+        self.hints = hints;
+        self
+    }
+
+    fn hint_factor(&self, hints: u32) -> f32 {
+        (1.0 - HINT_PENALTY * hints as f32).max(HINT_PENALTY)
+    }
+
+    /// Whether the answer is right, regardless of the hints used.
+    fn is_right(&self) -> bool {
+        self.score >= self.hint_factor(self.hints)
+    }
+}
+
+impl fmt::Display for RoundResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_feedback(f)?;
+        if self.hints > 0 {
+            write!(
+                f,
+                "\n  {} hint{} used, score {:.0}%",
+                self.hints,
+                if self.hints == 1 { "" } else { "s" },
+                self.score * 100.0
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl RoundResult {
+    fn fmt_feedback(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (answer, target) = (&self.answer, &self.target);
+        let notation = &self.notation;
+        let name = |x: u8| notation.note(Note(x));
+        match self.mode {
+            Mode::Note => {
+                let offset = octave_offset(answer[0], target[0]);
+                if self.is_right() {
+                    write!(
+                        f,
+                        "Correct, you played the right note ({})",
+                        name(answer[0])
+                    )?;
+                    match offset {
+                        Some(offset) => write!(f, " in another octave (off by {:+})", offset),
+                        None => Ok(()),
+                    }
+                } else if let Some(offset) = offset {
+                    write!(
+                        f,
+                        "Right note, wrong octave (off by {:+}): you played {}, but the right one is {}",
+                        offset,
+                        name(answer[0]),
+                        name(target[0])
+                    )
+                } else {
+                    write!(
+                        f,
+                        "Incorrect, you played {}, but the right one is {} ({})",
+                        name(answer[0]),
+                        name(target[0]),
+                        distance(answer[0], target[0])
+                    )
+                }
+            }
+            Mode::Interval => {
+                // The interval from the reference to a top note played alone.
+                let answer =
+                    &[&target[..unanswered_notes(self.mode, answer)], &answer[..]].concat();
+                let target_name = interval_name(target[0], target[1]);
+                if self.is_right() {
+                    write!(
+                        f,
+                        "Correct, it is a {} ({})",
+                        target_name,
+                        notation.notes(target)
+                    )
+                } else {
+                    write!(
+                        f,
+                        "Incorrect, you played a {} ({}), but the right one is a {} ({})",
+                        interval_name(answer[0], answer[1]),
+                        notation.notes(answer),
+                        target_name,
+                        notation.notes(target)
+                    )?;
+                    match octave_offset(answer[1], target[1]) {
+                        Some(offset) => {
+                            write!(f, "\n  right top note, wrong octave (off by {:+})", offset)
+                        }
+                        None if answer[1] != target[1] => {
+                            write!(f, "\n  the top note is {}", distance(answer[1], target[1]))
+                        }
+                        None => Ok(()),
+                    }
+                }
+            }
+            Mode::Chord => {
+                let chord_name = format!(
+                    "{} {}",
+                    notation.pitch_class(PitchClass(target[0] % SIGN_COUNT as u8)),
+                    self.chord.map_or("", |x| x.name)
+                );
+                let target_classes = pitch_classes(target);
+                let answer_classes = pitch_classes(answer);
+                if self.is_right() {
+                    return write!(
+                        f,
+                        "Correct, it is {} ({})",
+                        chord_name,
+                        notation.pitch_classes(&target_classes)
+                    );
+                }
+
+                write!(
+                    f,
+                    "Incorrect, the right chord is {} ({})",
+                    chord_name,
+                    notation.pitch_classes(&target_classes)
+                )?;
+                let missed: Vec<_> = target_classes.difference(&answer_classes).collect();
+                if !missed.is_empty() {
+                    write!(f, "\n  missed: {}", notation.pitch_classes(missed))?;
+                }
+                let extra: Vec<_> = answer_classes.difference(&target_classes).collect();
+                if !extra.is_empty() {
+                    write!(f, "\n  extra: {}", notation.pitch_classes(extra))?;
+                }
+                Ok(())
+            }
+            Mode::Melody => {
+                let mut correct = 0;
+                for (i, (&note, &target_note)) in answer.iter().zip(target).enumerate() {
+                    if note == target_note {
+                        correct += 1;
+                        writeln!(f, "{:>3}. {} ok", i + 1, name(note))?;
+                    } else if let Some(offset) = octave_offset(note, target_note) {
+                        writeln!(
+                            f,
+                            "{:>3}. {} right note, wrong octave (off by {:+})",
+                            i + 1,
+                            name(note),
+                            offset
+                        )?;
+                    } else {
+                        writeln!(
+                            f,
+                            "{:>3}. {} expected {} ({})",
+                            i + 1,
+                            name(note),
+                            name(target_note),
+                            distance(note, target_note)
+                        )?;
+                    }
+                }
+                if self.is_right() {
+                    write!(f, "Correct, you played the whole melody")
+                } else {
+                    write!(
+                        f,
+                        "Incorrect, you played {} of {} notes right",
+                        correct,
+                        target.len()
+                    )
+                }
+            }
+            Mode::Dynamics => {
+                let (target, answer) = match self.dynamics {
+                    Some(levels) => levels,
+                    None => return Ok(()),
+                };
+                if self.is_right() {
+                    return write!(f, "Correct, it is {}", target);
+                }
+                let steps = answer.steps_from(target);
+                write!(
+                    f,
+                    "Incorrect, you played {}, but the right one is {} ({} level{} too {})",
+                    answer,
+                    target,
+                    steps.abs(),
+                    if steps.abs() == 1 { "" } else { "s" },
+                    if steps > 0 { "loud" } else { "soft" }
+                )
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn melody_partial_credit() {
+        let session = Session::new(Settings {
+            mode: Mode::Melody,
+            ..Settings::default()
+        })
+        .unwrap();
+        let round = session.next_round();
+        let mut answer = round.target().to_vec();
+        answer[0] = answer[0].wrapping_add(1);
+
+        let result = round.submit(&answer);
+        assert!(!result.is_correct());
+        assert_eq!(result.score, 0.75);
+    }
+
+    #[test]
+    fn chord_in_other_octave() {
+        let session = Session::new(Settings {
+            mode: Mode::Chord,
+            ..Settings::default()
+        })
+        .unwrap();
+        let round = session.next_round();
+        let answer: Vec<u8> = round.target().iter().map(|x| x + 12).rev().collect();
+
+        assert!(round.submit(&answer).is_correct());
+    }
+
+    #[test]
+    fn wrong_octave_scoring() {
+        let round = |scoring| {
+            Session::new(Settings {
+                min_note: 60,
+                max_note: 60,
+                scoring,
+                ..Settings::default()
+            })
+            .unwrap()
+            .next_round()
+        };
+
+        let result = round(Scoring::Exact).submit(&[48]);
+        assert_eq!(result.score, 0.0);
+        assert!(result.to_string().contains("wrong octave (off by -1)"));
+        assert_eq!(round(Scoring::Partial).submit(&[72]).score, 0.5);
+        assert_eq!(round(Scoring::Partial).submit(&[61]).score, 0.0);
+        assert!(round(Scoring::PitchClass).submit(&[84]).is_correct());
+    }
+
+    #[test]
+    fn hints_reduce_score() {
+        let round = Session::new(Settings {
+            min_note: 60,
+            max_note: 60,
+            ..Settings::default()
+        })
+        .unwrap()
+        .next_round();
+
+        let result = round.submit(&[60]).with_hints(2);
+        assert_eq!(result.score, 0.5);
+        assert!(!result.is_correct());
+        assert!(result.to_string().starts_with("Correct"));
+        assert!(round
+            .submit(&[63])
+            .to_string()
+            .contains("3 semitones too high"));
+    }
+
+    #[test]
+    fn tonic_reference_is_near_the_middle() {
+        let session = Session::new(Settings {
+            key: Key(9),
+            reference: Some(Reference::Tonic),
+            ..Settings::default()
+        })
+        .unwrap();
+        assert_eq!(session.next_round().reference(), Some(69));
+
+        let notation = Notation {
+            middle_c_octave: 3,
+            ..Notation::default()
+        };
+        assert_eq!(Reference::parse("A3", &notation), Ok(Reference::Note(69)));
+        assert!(Reference::parse("A", &notation).is_err());
+    }
+
+    /// Dynamics round on middle C with a fixed level.
+    fn dynamics_round(dynamic: &str) -> Round {
+        Round {
+            mode: Mode::Dynamics,
+            target: vec![60],
+            chord: None,
+            dynamic: Some(dynamic.parse().unwrap()),
+            reference: None,
+            notation: Notation::default(),
+            scoring: Scoring::Exact,
+        }
+    }
+
+    #[test]
+    fn dynamics_scored_by_nearest_level() {
+        let round = dynamics_round("mp");
+        let result = round.submit(&[67]);
+        assert!(result.is_correct());
+        assert_eq!(result.target, vec![60]);
+        assert_eq!(result.to_string(), "Correct, it is mp");
+
+        let result = round.submit(&[30]);
+        assert!(!result.is_correct());
+        assert_eq!(
+            result.to_string(),
+            "Incorrect, you played pp, but the right one is mp (2 levels too soft)"
+        );
+        assert!(round
+            .submit(&[80])
+            .to_string()
+            .ends_with("(1 level too loud)"));
+    }
+}
@@ -0,0 +1,180 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::Context;
+use rustysynth::{Synthesizer, SynthesizerSettings};
+
+use crate::synth::Engine;
+
+pub use rustysynth::SoundFont;
+
+pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Arc<SoundFont>> {
+    let path = path.as_ref();
+    let file = File::open(path).with_context(|| format!("cannot open {}", path.display()))?;
+    let sound_font = SoundFont::new(&mut BufReader::new(file))
+        .with_context(|| format!("cannot load SoundFont {}", path.display()))?;
+    Ok(Arc::new(sound_font))
+}
+
+/// Synthesizer playing the presets of a SoundFont.
+///
+/// Reverb and chorus are disabled, so the same messages always render to
+/// the same samples.
+pub struct SoundFontSynth {
+    synth: Synthesizer,
+    left: Vec<f32>,
+    right: Vec<f32>,
+}
+
+impl SoundFontSynth {
+    pub fn new(sound_font: &Arc<SoundFont>, sample_rate: u32) -> anyhow::Result<Self> {
+        let mut settings = SynthesizerSettings::new(sample_rate as i32);
+        settings.enable_reverb_and_chorus = false;
+        Ok(SoundFontSynth {
+            synth: Synthesizer::new(sound_font, &settings)?,
+            left: Vec::new(),
+            right: Vec::new(),
+        })
+    }
+}
+
+impl Engine for SoundFontSynth {
+    fn handle(&mut self, message: &[u8]) {
+        let (status, data1, data2) = match *message {
+            [status, data1, data2] => (status, data1, data2),
+            [status, data1] => (status, data1, 0),
+            _ => return,
+        };
+        self.synth.process_midi_message(
+            i32::from(status & 0x0f),
+            i32::from(status & 0xf0),
+            i32::from(data1),
+            i32::from(data2),
+        );
+    }
+
+    fn render(&mut self, buffer: &mut [f32]) {
+        self.left.resize(buffer.len(), 0.0);
+        self.right.resize(buffer.len(), 0.0);
+        self.synth.render(&mut self.left, &mut self.right);
+        for (x, (l, r)) in buffer.iter_mut().zip(self.left.iter().zip(&self.right)) {
+            *x = ((l + r) / 2.0).clamp(-1.0, 1.0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::NOTE_ON;
+    use crate::synth::SAMPLE_RATE;
+
+    fn chunk(id: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut chunk = id.to_vec();
+        chunk.extend(&(data.len() as u32).to_le_bytes());
+        chunk.extend(data);
+        chunk
+    }
+
+    fn list(kind: &[u8; 4], chunks: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = kind.to_vec();
+        chunks.iter().for_each(|x| data.extend(x));
+        chunk(b"LIST", &data)
+    }
+
+    fn padded(name: &str) -> Vec<u8> {
+        let mut bytes = name.as_bytes().to_vec();
+        bytes.resize(20, 0);
+        bytes
+    }
+
+    fn words(words: &[u16]) -> Vec<u8> {
+        words
+            .iter()
+            .flat_map(|x| x.to_le_bytes().to_vec())
+            .collect()
+    }
+
+    fn sample_header(name: &str, start: u32, end: u32, original_pitch: u8) -> Vec<u8> {
+        let mut header = padded(name);
+        for &x in &[start, end, start, end, SAMPLE_RATE] {
+            header.extend(&x.to_le_bytes());
+        }
+        header.extend(&[original_pitch, 0]);
+        header.extend(&words(&[0, 1]));
+        header
+    }
+
+    /// SoundFont with a single preset looping one period of a square wave.
+    fn square_wave() -> Arc<SoundFont> {
+        let period = 100;
+        let mut samples: Vec<i16> = (0..period)
+            .map(|i| if i < period / 2 { 8000 } else { -8000 })
+            .collect();
+        samples.resize(period + 46, 0);
+        let samples: Vec<u8> = samples
+            .iter()
+            .flat_map(|x| x.to_le_bytes().to_vec())
+            .collect();
+
+        let preset = |name, bag| {
+            let mut info = padded(name);
+            info.extend(&words(&[0, 0, bag, 0, 0, 0, 0, 0, 0]));
+            info
+        };
+        let instrument = |name, bag| {
+            let mut info = padded(name);
+            info.extend(&words(&[bag]));
+            info
+        };
+        let pdta = [
+            chunk(b"phdr", &[preset("Square", 0), preset("EOP", 1)].concat()),
+            chunk(b"pbag", &words(&[0, 0, 1, 0])),
+            chunk(b"pmod", &[0; 10]),
+            // instrument 0
+            chunk(b"pgen", &words(&[41, 0, 0, 0])),
+            chunk(
+                b"inst",
+                &[instrument("Square", 0), instrument("EOI", 1)].concat(),
+            ),
+            chunk(b"ibag", &words(&[0, 0, 2, 0])),
+            chunk(b"imod", &[0; 10]),
+            // continuous loop, sample 0
+            chunk(b"igen", &words(&[54, 1, 53, 0, 0, 0])),
+            chunk(
+                b"shdr",
+                &[
+                    sample_header("Square", 0, period as u32, 60),
+                    sample_header("EOS", 0, 0, 0),
+                ]
+                .concat(),
+            ),
+        ];
+
+        let mut sfbk = b"sfbk".to_vec();
+        sfbk.extend(list(b"INFO", &[]));
+        sfbk.extend(list(b"sdta", &[chunk(b"smpl", &samples)]));
+        sfbk.extend(list(b"pdta", &pdta));
+        let file = chunk(b"RIFF", &sfbk);
+
+        Arc::new(SoundFont::new(&mut file.as_slice()).unwrap())
+    }
+
+    fn render(sound_font: &Arc<SoundFont>) -> Vec<f32> {
+        let mut synth = SoundFontSynth::new(sound_font, SAMPLE_RATE).unwrap();
+        let mut buffer = vec![0.0; SAMPLE_RATE as usize / 10];
+        synth.handle(&[NOTE_ON, 69, 0x40]);
+        synth.render(&mut buffer);
+        buffer
+    }
+
+    #[test]
+    fn rendering_is_deterministic() {
+        let sound_font = square_wave();
+        let buffer = render(&sound_font);
+        assert!(buffer.iter().any(|x| x.abs() > 0.01));
+        assert_eq!(buffer, render(&sound_font));
+    }
+}
@@ -0,0 +1,100 @@
+use std::fmt;
+use std::time::Duration;
+
+use crate::note::{Notation, PitchClass, SIGN_COUNT};
+use crate::session::{unanswered_notes, Mode, RoundResult};
+
+/// Totals of the rounds played in the current run.
+#[derive(Default)]
+pub struct Summary {
+    pub rounds: u32,
+    pub correct: u32,
+    /// Sum of scores, counting partially right answers.
+    pub credit: f32,
+    pub streak: u32,
+    pub best_streak: u32,
+    pub response_time: Duration,
+    /// Number of times a target pitch class (row) was answered with a played
+    /// one (column).
+    pub confusion: [[u32; SIGN_COUNT]; SIGN_COUNT],
+    pub notation: Notation,
+}
+
+impl Summary {
+    pub fn add(&mut self, result: &RoundResult, response_time: Duration) {
+        self.rounds += 1;
+        self.response_time += response_time;
+        self.credit += result.score;
+        if result.is_correct() {
+            self.correct += 1;
+            self.streak += 1;
+            self.best_streak = self.best_streak.max(self.streak);
+        } else {
+            self.streak = 0;
+        }
+
+        // Chord answers are not ordered, so there is no played note to pair
+        // each target note with. Dynamics rounds answer no notes at all.
+        if result.mode != Mode::Chord {
+            let skipped = unanswered_notes(result.mode, &result.answer);
+            for (&target, &answer) in result.target[skipped..].iter().zip(&result.answer) {
+                let target = usize::from(target) % SIGN_COUNT;
+                let answer = usize::from(answer) % SIGN_COUNT;
+                self.confusion[target][answer] += 1;
+            }
+        }
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, " ~~ Summary ~~")?;
+        if self.rounds == 0 {
+            return write!(f, "No rounds played");
+        }
+
+        writeln!(
+            f,
+            "Correct: {} of {} ({:.1}%)",
+            self.correct,
+            self.rounds,
+            self.correct as f32 / self.rounds as f32 * 100.0
+        )?;
+        if self.credit > self.correct as f32 {
+            writeln!(
+                f,
+                "With partial credit: {:.1} of {} ({:.1}%)",
+                self.credit,
+                self.rounds,
+                self.credit / self.rounds as f32 * 100.0
+            )?;
+        }
+        writeln!(f, "Best streak: {}", self.best_streak)?;
+        writeln!(
+            f,
+            "Average response time: {:.1}s",
+            self.response_time.as_secs_f32() / self.rounds as f32
+        )?;
+
+        let signs: Vec<String> = (0..SIGN_COUNT as u8)
+            .map(|x| self.notation.pitch_class(PitchClass(x)))
+            .collect();
+        let width = signs.iter().map(|x| x.chars().count()).max().unwrap_or(0) + 1;
+        write!(f, "\nTarget \\ played")?;
+        write!(f, "\n{:width$}", "", width = width)?;
+        for sign in &signs {
+            write!(f, "{:>width$}", sign, width = width + 1)?;
+        }
+        for (sign, row) in signs.iter().zip(&self.confusion) {
+            write!(f, "\n{:<width$}", sign, width = width)?;
+            for &count in row {
+                if count == 0 {
+                    write!(f, "{:>width$}", ".", width = width + 1)?;
+                } else {
+                    write!(f, "{:>width$}", count, width = width + 1)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
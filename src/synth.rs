@@ -0,0 +1,392 @@
+use std::f32::consts::PI;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Context;
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+
+use crate::backend::Output;
+use crate::midi::Message;
+use crate::soundfont::{SoundFont, SoundFontSynth};
+
+pub const SAMPLE_RATE: u32 = 44100;
+
+const ATTACK_S: f32 = 0.005;
+const RELEASE_S: f32 = 0.1;
+/// Time for a piano-like note to decay by `e`.
+const PIANO_DECAY_S: f32 = 0.8;
+/// Level below which a released voice is dropped.
+const SILENCE: f32 = 1e-4;
+const SUSTAIN_PEDAL: u8 = 64;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Waveform {
+    Sine,
+    Triangle,
+    /// Decaying tone with a few harmonics.
+    Piano,
+}
+
+impl FromStr for Waveform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sine" => Ok(Waveform::Sine),
+            "triangle" => Ok(Waveform::Triangle),
+            "piano" => Ok(Waveform::Piano),
+            _ => Err(format!(
+                "unknown waveform `{}`, expected sine, triangle or piano",
+                s
+            )),
+        }
+    }
+}
+
+/// Sound generator driven by MIDI messages.
+pub trait Engine: Send {
+    fn handle(&mut self, message: &[u8]);
+
+    /// Fills `buffer` with mono samples in `-1..=1`.
+    fn render(&mut self, buffer: &mut [f32]);
+}
+
+/// What the built-in synthesizer sounds like.
+#[derive(Clone)]
+pub enum Sound {
+    Waveform(Waveform),
+    SoundFont(Arc<SoundFont>),
+}
+
+impl Sound {
+    pub fn engine(&self, sample_rate: u32) -> anyhow::Result<Box<dyn Engine>> {
+        Ok(match self {
+            Sound::Waveform(waveform) => Box::new(Synth::new(sample_rate, *waveform)),
+            Sound::SoundFont(sound_font) => Box::new(SoundFontSynth::new(sound_font, sample_rate)?),
+        })
+    }
+}
+
+pub fn note_frequency(note: u8) -> f32 {
+    440.0 * 2f32.powf((f32::from(note) - 69.0) / 12.0)
+}
+
+struct Voice {
+    note: u8,
+    amplitude: f32,
+    /// Seconds since the note on.
+    time: f32,
+    /// Level and time of the note off.
+    released: Option<(f32, f32)>,
+    /// Note off received while the sustain pedal is down.
+    pedal_held: bool,
+}
+
+impl Voice {
+    fn oscillator(&self, waveform: Waveform) -> f32 {
+        let phase = (self.time * note_frequency(self.note)).fract();
+        match waveform {
+            Waveform::Sine => (2.0 * PI * phase).sin(),
+            Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+            Waveform::Piano => {
+                let x = 2.0 * PI * phase;
+                (x.sin() + 0.5 * (2.0 * x).sin() + 0.25 * (3.0 * x).sin()) / 1.75
+            }
+        }
+    }
+
+    fn release(&mut self, waveform: Waveform) {
+        if self.released.is_none() {
+            self.released = Some((self.envelope(waveform), self.time));
+        }
+    }
+
+    fn envelope(&self, waveform: Waveform) -> f32 {
+        let held = (self.time / ATTACK_S).min(1.0)
+            * match waveform {
+                Waveform::Piano => (-self.time / PIANO_DECAY_S).exp(),
+                _ => 1.0,
+            };
+        match self.released {
+            Some((level, at)) => level * (1.0 - (self.time - at) / RELEASE_S).max(0.0),
+            None => held,
+        }
+    }
+}
+
+/// Polyphonic synthesizer driven by note on and off and sustain pedal
+/// messages of any channel.
+pub struct Synth {
+    sample_rate: u32,
+    waveform: Waveform,
+    voices: Vec<Voice>,
+    pedal: bool,
+}
+
+impl Synth {
+    pub fn new(sample_rate: u32, waveform: Waveform) -> Self {
+        Synth {
+            sample_rate,
+            waveform,
+            voices: Vec::new(),
+            pedal: false,
+        }
+    }
+}
+
+impl Engine for Synth {
+    fn handle(&mut self, message: &[u8]) {
+        match Message::parse(message) {
+            Some(Message::NoteOn { note, velocity, .. }) if velocity != 0 => {
+                self.voices.push(Voice {
+                    note,
+                    amplitude: f32::from(velocity) / 127.0,
+                    time: 0.0,
+                    released: None,
+                    pedal_held: false,
+                });
+            }
+            Some(Message::NoteOn { note, .. }) | Some(Message::NoteOff { note, .. }) => {
+                let (waveform, pedal) = (self.waveform, self.pedal);
+                for voice in self.voices.iter_mut().filter(|x| x.note == note) {
+                    if pedal {
+                        voice.pedal_held = true;
+                    } else {
+                        voice.release(waveform);
+                    }
+                }
+            }
+            Some(Message::ControlChange {
+                controller: SUSTAIN_PEDAL,
+                value,
+                ..
+            }) => {
+                self.pedal = value >= 64;
+                if !self.pedal {
+                    let waveform = self.waveform;
+                    for voice in self.voices.iter_mut().filter(|x| x.pedal_held) {
+                        voice.release(waveform);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn render(&mut self, buffer: &mut [f32]) {
+        let step = 1.0 / self.sample_rate as f32;
+        let waveform = self.waveform;
+        for sample in buffer.iter_mut() {
+            *sample = 0.0;
+            for voice in &mut self.voices {
+                *sample +=
+                    0.25 * voice.amplitude * voice.envelope(waveform) * voice.oscillator(waveform);
+                voice.time += step;
+            }
+            *sample = sample.clamp(-1.0, 1.0);
+        }
+        self.voices
+            .retain(|x| x.released.is_none() || x.envelope(waveform) > SILENCE);
+    }
+}
+
+/// Plays the synthesizer through the default audio device.
+pub struct AudioOutput {
+    synth: Arc<Mutex<Box<dyn Engine>>>,
+    _stream: cpal::Stream,
+}
+
+impl AudioOutput {
+    pub fn new(sound: &Sound) -> anyhow::Result<Self> {
+        let device = cpal::default_host()
+            .default_output_device()
+            .context("No audio output device found")?;
+        let config = device.default_output_config()?;
+        let channels = usize::from(config.channels());
+        let synth = Arc::new(Mutex::new(sound.engine(config.sample_rate().0)?));
+
+        macro_rules! build_stream {
+            ($sample:ty, $convert:expr) => {{
+                let synth = synth.clone();
+                let mut mono = Vec::new();
+                device.build_output_stream(
+                    &config.config(),
+                    move |data: &mut [$sample], _| {
+                        mono.resize(data.len() / channels, 0.0);
+                        synth.lock().unwrap().render(&mut mono);
+                        for (frame, &x) in data.chunks_mut(channels).zip(&mono) {
+                            frame.iter_mut().for_each(|y| *y = $convert(x));
+                        }
+                    },
+                    |e| eprintln!("Audio output error: {}", e),
+                )?
+            }};
+        }
+
+        let stream = match config.sample_format() {
+            cpal::SampleFormat::F32 => build_stream!(f32, |x: f32| x),
+            cpal::SampleFormat::I16 => {
+                build_stream!(i16, |x: f32| (x * f32::from(i16::MAX)) as i16)
+            }
+            cpal::SampleFormat::U16 => {
+                build_stream!(u16, |x: f32| ((x + 1.0) * f32::from(i16::MAX)) as u16)
+            }
+        };
+        stream.play()?;
+
+        Ok(AudioOutput {
+            synth,
+            _stream: stream,
+        })
+    }
+}
+
+impl Output for AudioOutput {
+    fn send(&mut self, message: &[u8]) -> anyhow::Result<()> {
+        self.synth.lock().unwrap().handle(message);
+        Ok(())
+    }
+}
+
+/// Renders the synthesizer into a WAV file on its own sample clock, which
+/// only advances by the waits between messages.
+///
+/// The time spent waiting for answers is left out, so the same notes always
+/// render to the same file. The file header is updated after every wait, so
+/// the file stays valid even if the process is killed.
+pub struct WavOutput {
+    synth: Box<dyn Engine>,
+    writer: hound::WavWriter<std::io::BufWriter<std::fs::File>>,
+}
+
+impl WavOutput {
+    pub fn create(path: impl AsRef<Path>, sound: &Sound) -> anyhow::Result<Self> {
+        let spec = hound::WavSpec {
+            channels: 1,
+            sample_rate: SAMPLE_RATE,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let writer = hound::WavWriter::create(path.as_ref(), spec)
+            .with_context(|| format!("cannot create {}", path.as_ref().display()))?;
+
+        Ok(WavOutput {
+            synth: sound.engine(SAMPLE_RATE)?,
+            writer,
+        })
+    }
+
+    fn render(&mut self, duration: Duration) -> anyhow::Result<()> {
+        let length = (duration.as_secs_f64() * f64::from(SAMPLE_RATE)).round() as usize;
+        let mut buffer = vec![0.0; length];
+        self.synth.render(&mut buffer);
+        for x in buffer {
+            self.writer.write_sample((x * f32::from(i16::MAX)) as i16)?;
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+impl Output for WavOutput {
+    fn send(&mut self, message: &[u8]) -> anyhow::Result<()> {
+        self.synth.handle(message);
+        Ok(())
+    }
+
+    fn wait(&mut self, duration: Duration) -> anyhow::Result<()> {
+        self.render(duration)
+    }
+}
+
+impl Drop for WavOutput {
+    fn drop(&mut self) {
+        // Let the last released notes fade out.
+        let _ = self.render(Duration::from_secs_f32(RELEASE_S));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{NOTE_OFF, NOTE_ON};
+
+    fn peak(buffer: &[f32]) -> f32 {
+        buffer.iter().fold(0.0, |x, y| x.max(y.abs()))
+    }
+
+    #[test]
+    fn note_sounds_until_released() {
+        for &waveform in &[Waveform::Sine, Waveform::Triangle, Waveform::Piano] {
+            let mut synth = Synth::new(SAMPLE_RATE, waveform);
+            let mut buffer = vec![0.0; SAMPLE_RATE as usize / 10];
+
+            synth.handle(&[NOTE_ON, 69, 0x40]);
+            synth.render(&mut buffer);
+            assert!(peak(&buffer) > 0.05);
+
+            synth.handle(&[NOTE_OFF, 69, 0x40]);
+            synth.render(&mut buffer);
+            synth.render(&mut buffer);
+            assert_eq!(peak(&buffer), 0.0);
+        }
+    }
+
+    #[test]
+    fn pedal_holds_released_notes() {
+        let mut synth = Synth::new(SAMPLE_RATE, Waveform::Sine);
+        let mut buffer = vec![0.0; SAMPLE_RATE as usize / 5];
+
+        synth.handle(&[0xb0, SUSTAIN_PEDAL, 127]);
+        synth.handle(&[NOTE_ON, 69, 0x40]);
+        synth.handle(&[NOTE_OFF, 69, 0x40]);
+        synth.render(&mut buffer);
+        assert!(peak(&buffer) > 0.05);
+
+        synth.handle(&[0xb0, SUSTAIN_PEDAL, 0]);
+        synth.render(&mut buffer);
+        synth.render(&mut buffer);
+        assert_eq!(peak(&buffer), 0.0);
+    }
+
+    #[test]
+    fn wav_follows_played_durations() {
+        let path = std::env::temp_dir().join(format!("guess-note-{}.wav", std::process::id()));
+        let render = || {
+            let mut output = WavOutput::create(&path, &Sound::Waveform(Waveform::Sine)).unwrap();
+            output.send(&[NOTE_ON, 69, 0x40]).unwrap();
+            output.wait(Duration::from_millis(200)).unwrap();
+            output.send(&[NOTE_OFF, 69, 0x40]).unwrap();
+            drop(output);
+
+            let mut reader = hound::WavReader::open(&path).unwrap();
+            let samples: Vec<i16> = reader.samples().map(Result::unwrap).collect();
+            samples
+        };
+
+        let samples = render();
+        let fade_out = (RELEASE_S * SAMPLE_RATE as f32) as usize;
+        assert_eq!(samples.len(), SAMPLE_RATE as usize / 5 + fade_out);
+        let peak = samples.iter().map(|x| x.unsigned_abs()).max().unwrap();
+        // A voice at velocity 64 plays at about an eighth of full scale.
+        assert!((3500..=4200).contains(&peak), "peak {}", peak);
+        assert_eq!(samples.last(), Some(&0));
+        assert_eq!(render(), samples);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn rendering_is_deterministic() {
+        let render = || {
+            let mut synth = Synth::new(SAMPLE_RATE, Waveform::Piano);
+            let mut buffer = vec![0.0; 1000];
+            synth.handle(&[NOTE_ON, 60, 0x40]);
+            synth.handle(&[NOTE_ON, 64, 0x40]);
+            synth.render(&mut buffer);
+            buffer
+        };
+        assert_eq!(render(), render());
+    }
+}